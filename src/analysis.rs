@@ -0,0 +1,752 @@
+//! Whole-map analyses over the graph of `Named` references
+//!
+//! Items are nodes and references between them are edges. Results are
+//! computed once per `ItemMap` using strongly connected components, so
+//! mutually recursive items terminate and the cost is linear in the size
+//! of the map.
+
+use std::collections::BTreeMap;
+
+use {Derive, Id, Item, ItemKind, ItemMap, Type};
+
+/// Memoized analyses of an `ItemMap`, see `ItemMap::analysis`
+pub struct Analysis {
+    index: BTreeMap<Id, usize>,
+    defaultable: Vec<bool>,
+    /// Whether each item can derive each standard trait, except `Default`
+    derivable: BTreeMap<Derive, Vec<bool>>,
+    recursive: Vec<bool>,
+    /// The strongly connected component of each item, following edges to
+    /// items stored inline (i.e. not behind a pointer). Edges only lead
+    /// to components with lower numbers.
+    component: Vec<usize>,
+    /// Edges between components
+    condensed: Vec<Vec<usize>>,
+}
+
+impl Analysis {
+    pub(crate) fn new(map: &ItemMap) -> Analysis {
+        let index: BTreeMap<Id, usize> = map.iter()
+            .enumerate()
+            .map(|(ix, (id, _))| (id.clone(), ix))
+            .collect();
+        let len = index.len();
+        let mut local_default = vec![true; len];
+        let mut default_edges = vec![Vec::new(); len];
+        let mut unboxed = vec![Vec::new(); len];
+        for (ix, (_, item)) in map.iter().enumerate() {
+            match item.derive_deps(&Derive::Default) {
+                Some(deps) => {
+                    for dep in deps {
+                        match dep_index(&index, dep) {
+                            Some(dep) => default_edges[ix].push(dep),
+                            None => {
+                                if !map.external_derives(dep, &Derive::Default) {
+                                    local_default[ix] = false
+                                }
+                            }
+                        }
+                    }
+                }
+                None => local_default[ix] = false,
+            }
+            unboxed[ix] = item.unboxed_ids()
+                .into_iter()
+                .filter_map(|id| index.get(id).cloned())
+                .collect();
+        }
+
+        // components arrive dependencies-first, so every dependency
+        // outside the current component has already been decided
+        let mut defaultable = vec![false; len];
+        for component in strongly_connected(&default_edges) {
+            if is_cycle(&component, &default_edges) {
+                continue;
+            }
+            let node = component[0];
+            defaultable[node] = local_default[node] &&
+                default_edges[node].iter().all(|&dep| defaultable[dep]);
+        }
+
+        // apart from `Default`, derives on recursive types are fine, so
+        // items are derivable unless something they depend on is not
+        let derivable = Derive::standard()
+            .into_iter()
+            .filter(|derive| *derive != Derive::Default)
+            .map(|derive| {
+                let ok = derivable(map, &index, &derive);
+                (derive, ok)
+            })
+            .collect();
+
+        let mut recursive = vec![false; len];
+        let mut component = vec![0; len];
+        let components = strongly_connected(&unboxed);
+        for (ix, members) in components.iter().enumerate() {
+            let cycle = is_cycle(members, &unboxed);
+            for &node in members {
+                recursive[node] = cycle;
+                component[node] = ix;
+            }
+        }
+        let mut condensed = vec![Vec::new(); components.len()];
+        for (from, targets) in unboxed.iter().enumerate() {
+            for &to in targets {
+                if component[from] != component[to] {
+                    condensed[component[from]].push(component[to]);
+                }
+            }
+        }
+        for targets in &mut condensed {
+            targets.sort();
+            targets.dedup();
+        }
+
+        Analysis {
+            index,
+            defaultable,
+            derivable,
+            recursive,
+            component,
+            condensed,
+        }
+    }
+
+    /// Whether the item can `#[derive(Default)]`. Items which (transitively)
+    /// need themselves to be `Default`, or which refer to types outside the
+    /// map that are not declared as `External` types implementing it, are not.
+    pub fn is_defaultable(&self, id: &Id) -> bool {
+        self.index.get(id).map(|&ix| self.defaultable[ix]).unwrap_or(false)
+    }
+
+    /// Whether the item can `#[derive]` the given standard trait.
+    /// Custom derives are never considered derivable.
+    pub fn is_derivable(&self, id: &Id, derive: &Derive) -> bool {
+        if *derive == Derive::Default {
+            return self.is_defaultable(id);
+        }
+        match (self.index.get(id), self.derivable.get(derive)) {
+            (Some(&ix), Some(ok)) => ok[ix],
+            _ => false,
+        }
+    }
+
+    /// All the standard traits the item can `#[derive]`
+    pub fn derivable(&self, id: &Id) -> Vec<Derive> {
+        Derive::standard()
+            .into_iter()
+            .filter(|derive| self.is_derivable(id, derive))
+            .collect()
+    }
+
+    /// Whether the item contains itself without indirection,
+    /// and so has infinite size
+    pub fn is_recursive(&self, id: &Id) -> bool {
+        self.index.get(id).map(|&ix| self.recursive[ix]).unwrap_or(false)
+    }
+
+    /// Whether `target` is stored inline somewhere within `id`. Answered
+    /// with at most one walk over the components between the two items.
+    pub fn contains_unboxed_id(&self, id: &Id, target: &Id) -> bool {
+        let (from, target) = match (self.index.get(id), self.index.get(target)) {
+            (Some(&from), Some(&target)) => (from, target),
+            _ => return false,
+        };
+        let (from_component, target_component) = (self.component[from], self.component[target]);
+        if from_component == target_component {
+            // items in a cycle reach each other, and themselves
+            return self.recursive[from];
+        }
+        if target_component > from_component {
+            return false;
+        }
+        let mut seen = vec![false; self.condensed.len()];
+        let mut stack = self.condensed[from_component].clone();
+        while let Some(node) = stack.pop() {
+            if node == target_component {
+                return true;
+            }
+            // anything numbered below the target cannot lead back up to it
+            if node > target_component && !seen[node] {
+                seen[node] = true;
+                stack.extend(&self.condensed[node]);
+            }
+        }
+        false
+    }
+}
+
+/// The item a `derive_deps` type refers to, if it is in the map
+fn dep_index(index: &BTreeMap<Id, usize>, dep: &Type) -> Option<usize> {
+    match *dep {
+        Type::Named(ref id) => index.get(id).cloned(),
+        _ => None,
+    }
+}
+
+/// Which items can derive `derive`, assuming that cycles are fine
+fn derivable(map: &ItemMap, index: &BTreeMap<Id, usize>, derive: &Derive) -> Vec<bool> {
+    let len = index.len();
+    let mut ok = vec![true; len];
+    let mut users = vec![Vec::new(); len];
+    for (ix, (_, item)) in map.iter().enumerate() {
+        match item.derive_deps(derive) {
+            Some(deps) => {
+                for dep in deps {
+                    match dep_index(index, dep) {
+                        Some(dep) => users[dep].push(ix),
+                        None => {
+                            if !map.external_derives(dep, derive) {
+                                ok[ix] = false
+                            }
+                        }
+                    }
+                }
+            }
+            None => ok[ix] = false,
+        }
+    }
+    let mut failed: Vec<usize> = (0..len).filter(|&ix| !ok[ix]).collect();
+    while let Some(node) = failed.pop() {
+        for &user in &users[node] {
+            if ok[user] {
+                ok[user] = false;
+                failed.push(user);
+            }
+        }
+    }
+    ok
+}
+
+/// Groups of items which are identical apart from their names, each in
+/// insertion order. Items start out grouped by their shape and groups are
+/// split until every reference from members of a group leads to the same
+/// group, so recursive items are compared correctly. `Const`s are skipped.
+pub(crate) fn duplicates(map: &ItemMap) -> Vec<Vec<Id>> {
+    let ids: Vec<&Id> = map.insertion_order()
+        .iter()
+        .filter(|id| !matches!(map.get(id).unwrap().kind(), ItemKind::Const(_)))
+        .collect();
+    let mut class: BTreeMap<Id, usize> = ids.iter().map(|&id| (id.clone(), 0)).collect();
+    let mut count = 1;
+    loop {
+        let mut classes = BTreeMap::new();
+        let mut next = BTreeMap::new();
+        for &id in &ids {
+            let key = (class[id], signature(&**map.get(id).unwrap(), &class));
+            let len = classes.len();
+            let new = *classes.entry(key).or_insert(len);
+            next.insert(id.clone(), new);
+        }
+        class = next;
+        if classes.len() == count {
+            break;
+        }
+        count = classes.len();
+    }
+    let mut groups = vec![Vec::new(); count];
+    for id in ids {
+        groups[class[id]].push(id.clone());
+    }
+    groups.into_iter().filter(|group| group.len() > 1).collect()
+}
+
+/// The item rendered with its own name removed, and references to other
+/// items replaced by their class
+fn signature(item: &Item, class: &BTreeMap<Id, usize>) -> String {
+    let mut copy: Box<Item> = match item.kind() {
+        ItemKind::Struct(s) => Box::new(s.clone()),
+        ItemKind::Enum(e) => Box::new(e.clone()),
+        ItemKind::NewType(n) => Box::new(n.clone()),
+        ItemKind::Alias(a) => Box::new(a.clone()),
+        ItemKind::Const(c) => Box::new(c.clone()),
+    };
+    // '#' cannot appear in a real identifier
+    *copy.kind_mut().name_mut() = Id("#".into());
+    let rename = |id: &Id| class.get(id).map(|class| Id(format!("#{}", class)));
+    for (_, typ) in copy.kind_mut().members() {
+        typ.rename_ids(&rename);
+    }
+    copy.to_string()
+}
+
+/// References which must be boxed for every item to have a finite size,
+/// as (item, index into `ItemKind::members`, target). Each reference inside
+/// a cycle is kept inline unless the references kept so far already lead
+/// from its target back to its item, so the set is minimal: unboxing any
+/// one of them would make some item infinitely sized again.
+pub(crate) fn boxing_plan(map: &ItemMap) -> Vec<(Id, usize, Id)> {
+    let ids: Vec<&Id> = map.iter().map(|(id, _)| id).collect();
+    let index: BTreeMap<&Id, usize> = ids.iter().enumerate().map(|(ix, &id)| (id, ix)).collect();
+    let len = ids.len();
+    let mut edges = vec![Vec::new(); len];
+    let mut refs = Vec::new();
+    for (from, (_, item)) in map.iter().enumerate() {
+        for (member, (_, typ)) in item.kind().members().into_iter().enumerate() {
+            let mut targets: Vec<usize> = typ.unboxed_ids()
+                .into_iter()
+                .filter_map(|id| index.get(id).cloned())
+                .collect();
+            targets.sort();
+            targets.dedup();
+            for to in targets {
+                edges[from].push(to);
+                refs.push((from, member, to));
+            }
+        }
+    }
+
+    let mut component = vec![0; len];
+    for (ix, members) in strongly_connected(&edges).into_iter().enumerate() {
+        for node in members {
+            component[node] = ix;
+        }
+    }
+    let mut kept = vec![Vec::new(); len];
+    let mut plan = Vec::new();
+    for (from, member, to) in refs {
+        if component[from] != component[to] {
+            continue;
+        }
+        if reaches(&kept, to, from) {
+            plan.push((ids[from].clone(), member, ids[to].clone()));
+        } else {
+            kept[from].push(to);
+        }
+    }
+    plan
+}
+
+/// Whether `to` can be reached from `from` (including when they are equal)
+fn reaches(edges: &[Vec<usize>], from: usize, to: usize) -> bool {
+    let mut seen = vec![false; edges.len()];
+    let mut stack = vec![from];
+    while let Some(node) = stack.pop() {
+        if node == to {
+            return true;
+        }
+        if !seen[node] {
+            seen[node] = true;
+            stack.extend(&edges[node]);
+        }
+    }
+    false
+}
+
+/// Whether a strongly connected component contains a cycle
+pub(crate) fn is_cycle(component: &[usize], edges: &[Vec<usize>]) -> bool {
+    component.len() > 1 || edges[component[0]].contains(&component[0])
+}
+
+/// Tarjan's algorithm, without recursion so deep graphs cannot overflow
+/// the stack. Components are returned in reverse topological order: every
+/// component comes after all the components it has edges to.
+pub(crate) fn strongly_connected(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let len = edges.len();
+    let mut index = vec![UNVISITED; len];
+    let mut lowlink = vec![0; len];
+    let mut on_stack = vec![false; len];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut counter = 0;
+    for root in 0..len {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // (node, next edge to explore)
+        let mut work = vec![(root, 0)];
+        while let Some((node, edge)) = work.pop() {
+            if edge == 0 {
+                index[node] = counter;
+                lowlink[node] = counter;
+                counter += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+            if edge < edges[node].len() {
+                let next = edges[node][edge];
+                work.push((node, edge + 1));
+                if index[next] == UNVISITED {
+                    work.push((next, 0));
+                } else if on_stack[next] {
+                    lowlink[node] = lowlink[node].min(index[next]);
+                }
+                continue;
+            }
+            if lowlink[node] == index[node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+            if let Some(&(parent, _)) = work.last() {
+                lowlink[parent] = lowlink[parent].min(lowlink[node]);
+            }
+        }
+    }
+    components
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use {Attributes, Enum, External, Field, Item, ItemKind, NewType, Path, Primitive, Struct, Type,
+         TypeId, ValueId, Variant, Visibility};
+
+    /// A public struct with the given fields, for tests here and in `graph`
+    pub(crate) fn strukt(name: &str, fields: Vec<(&str, Type)>) -> Box<Item> {
+        let fields = fields
+            .into_iter()
+            .map(|(name, typ)| Field::new(ValueId::new(name).unwrap(), typ, vec![]))
+            .collect();
+        Box::new(Struct::new(
+            TypeId::new(name).unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            fields,
+        ).unwrap())
+    }
+
+    pub(crate) fn named(name: &str) -> Type {
+        Type::named(name).unwrap()
+    }
+
+    fn id(name: &str) -> Id {
+        Id::new(name).unwrap()
+    }
+
+    fn type_id(name: &str) -> TypeId {
+        TypeId::new(name).unwrap()
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        let map = ItemMap::build(vec![
+            strukt("A", vec![("b", named("B").optional(true))]),
+            strukt("B", vec![("a", named("A").optional(true))]),
+            strukt("C", vec![("d", Type::Box(Box::new(named("D"))))]),
+            strukt("D", vec![("c", Type::Box(Box::new(named("C"))))]),
+            strukt("E", vec![("e", Type::Vec(Box::new(named("E"))))]),
+            strukt("F", vec![("a", named("A")), ("missing", named("Missing"))]),
+            Box::new(NewType::new(
+                type_id("G"),
+                Visibility::Public,
+                Attributes::default(),
+                Type::Result(Box::new(named("G")), Box::new(named("A"))),
+            )),
+            Box::new(Enum::new(
+                type_id("H"),
+                Visibility::Public,
+                Attributes::default(),
+                vec![Variant::new(type_id("H1"), Some(named("A")), vec![])],
+            ).unwrap()),
+        ]).unwrap();
+        let analysis = map.analysis();
+
+        assert!(analysis.is_recursive(&id("A")));
+        assert!(analysis.is_recursive(&id("B")));
+        assert!(!analysis.is_recursive(&id("C")));
+        assert!(!analysis.is_recursive(&id("E")));
+        assert!(analysis.is_recursive(&id("G")));
+        assert!(map.get(&id("A")).unwrap().is_recursive(&map));
+        assert!(!map.get(&id("F")).unwrap().is_recursive(&map));
+
+        assert!(analysis.contains_unboxed_id(&id("F"), &id("B")));
+        assert!(analysis.contains_unboxed_id(&id("H"), &id("A")));
+        assert!(!analysis.contains_unboxed_id(&id("C"), &id("D")));
+        assert!(analysis.contains_unboxed_id(&id("A"), &id("A")));
+        assert!(analysis.contains_unboxed_id(&id("G"), &id("B")));
+        assert!(!analysis.contains_unboxed_id(&id("B"), &id("F")));
+        assert!(!analysis.contains_unboxed_id(&id("F"), &id("F")));
+        assert!(!analysis.contains_unboxed_id(&id("H"), &id("G")));
+
+        assert!(analysis.is_defaultable(&id("A")));
+        assert!(analysis.is_defaultable(&id("B")));
+        assert!(!analysis.is_defaultable(&id("C")));
+        assert!(!analysis.is_defaultable(&id("D")));
+        assert!(analysis.is_defaultable(&id("E")));
+        assert!(!analysis.is_defaultable(&id("F")));
+        assert!(!analysis.is_defaultable(&id("G")));
+        assert!(!analysis.is_defaultable(&id("H")));
+        assert!(!analysis.is_defaultable(&id("Missing")));
+    }
+
+    #[test]
+    fn test_box_recursive() {
+        let mut map = ItemMap::build(vec![
+            strukt("List", vec![("next", named("List").optional(true))]),
+            strukt("A", vec![("b", named("B")), ("c", named("C"))]),
+            strukt("B", vec![("a", named("A").optional(true))]),
+            strukt("C", vec![("a", Type::Result(Box::new(named("A")), Box::new(named("A"))))]),
+            strukt("D", vec![("a", named("A"))]),
+            Box::new(Enum::new(
+                type_id("Tree"),
+                Visibility::Public,
+                Attributes::default(),
+                vec![
+                    Variant::new(type_id("Leaf"), None, vec![]),
+                    Variant::new(type_id("Node"), Some(named("Tree")), vec![]),
+                ],
+            ).unwrap()),
+        ]).unwrap();
+        assert!(map.analysis().is_recursive(&id("A")));
+        let changes: Vec<String> = map.box_recursive()
+            .iter()
+            .map(|boxed| boxed.to_string())
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                "B.a: Box<A>",
+                "C.a: Box<A>",
+                "List.next: Box<List>",
+                "Tree.Node: Box<Tree>",
+            ]
+        );
+        for name in &["A", "B", "C", "D", "List", "Tree"] {
+            assert!(!map.analysis().is_recursive(&id(name)));
+        }
+        let member_type = |name: &str| {
+            map.get(&id(name)).unwrap().kind().members()[0].1.to_string()
+        };
+        assert_eq!(member_type("C"), "Result<Box<A>, Box<A>>");
+        assert_eq!(member_type("List"), "Option<Box<List>>");
+        assert_eq!(member_type("D"), "A");
+        assert!(map.box_recursive().is_empty());
+    }
+
+    #[test]
+    fn test_derive_inference() {
+        use Derive::*;
+        let mut map = ItemMap::build(vec![
+            strukt("Point", vec![("x", Type::Primitive(Primitive::I32))]),
+            strukt("Float", vec![("x", Type::Primitive(Primitive::F64))]),
+            strukt("Name", vec![("name", Type::Primitive(Primitive::String))]),
+            strukt("List", vec![("next", Type::Box(Box::new(named("List"))).optional(true))]),
+            strukt(
+                "Lookup",
+                vec![
+                    ("table", Type::Map(
+                        Box::new(Type::Primitive(Primitive::String)),
+                        Box::new(named("Point")),
+                    )),
+                ],
+            ),
+            strukt(
+                "Outcome",
+                vec![("res", Type::Result(Box::new(named("Point")), Box::new(named("Name"))))],
+            ),
+            strukt("Wrapper", vec![("float", named("Float")), ("other", named("Missing"))]),
+            Box::new(Enum::new(
+                type_id("Shape"),
+                Visibility::Public,
+                Attributes::default().derive(&[Debug]),
+                vec![
+                    Variant::new(type_id("Empty"), None, vec![]),
+                    Variant::new(type_id("Dot"), Some(named("Point")), vec![]),
+                ],
+            ).unwrap()),
+        ]).unwrap();
+
+        let analysis = map.analysis();
+        assert_eq!(analysis.derivable(&id("Point")), Derive::standard());
+        assert_eq!(
+            analysis.derivable(&id("Float")),
+            vec![Debug, Copy, Clone, PartialEq, PartialOrd, Default]
+        );
+        assert_eq!(
+            analysis.derivable(&id("Name")),
+            vec![Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default]
+        );
+        assert_eq!(
+            analysis.derivable(&id("List")),
+            vec![Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default]
+        );
+        assert_eq!(
+            analysis.derivable(&id("Lookup")),
+            vec![Debug, Clone, PartialEq, Eq, Default]
+        );
+        assert_eq!(
+            analysis.derivable(&id("Outcome")),
+            vec![Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord]
+        );
+        assert_eq!(analysis.derivable(&id("Wrapper")), vec![]);
+        assert_eq!(
+            analysis.derivable(&id("Shape")),
+            vec![Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord]
+        );
+
+        let serialize = Derive::Custom("Serialize".into());
+        let withheld = map.apply_derives(&[Clone, Copy, Eq, Default, serialize.clone()]);
+        assert_eq!(withheld[&id("Point")], vec![Eq]);
+        assert_eq!(withheld[&id("Name")], vec![Copy, Eq]);
+        assert_eq!(withheld[&id("Wrapper")], vec![Clone, Copy, Eq, Default]);
+        assert_eq!(withheld[&id("Shape")], vec![Eq, Default]);
+        match map.get(&id("Shape")).unwrap().kind() {
+            ItemKind::Enum(e) => {
+                let derives: Vec<&Derive> = e.attrs.derive.iter().collect();
+                assert_eq!(derives, vec![&Debug, &Copy, &Clone, &serialize]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_unresolved() {
+        let mut map = ItemMap::build(vec![
+            strukt(
+                "Order",
+                vec![
+                    ("placed", named("DateTime")),
+                    ("lines", Type::Map(Box::new(named("Sku")), Box::new(named("Line")))),
+                    ("status", Type::Result(Box::new(named("Status")), Box::new(named("Error")))),
+                ],
+            ),
+            strukt("Status", vec![]),
+            Box::new(NewType::new(
+                type_id("Lines"),
+                Visibility::Public,
+                Attributes::default(),
+                Type::Vec(Box::new(named("Line"))),
+            )),
+        ]).unwrap();
+        let unresolved: Vec<String> = map.unresolved().iter().map(|u| u.to_string()).collect();
+        assert_eq!(
+            unresolved,
+            vec![
+                "Lines -> Line",
+                "Order.placed -> DateTime",
+                "Order.lines -> Sku",
+                "Order.lines -> Line",
+                "Order.status -> Error",
+            ]
+        );
+        assert!(map.validate().is_err());
+        assert!(!map.analysis().is_defaultable(&id("Order")));
+
+        use Derive::*;
+        for (name, derives) in [
+            ("DateTime", vec![Debug, Clone, Copy, Default]),
+            ("Sku", vec![Debug, Clone]),
+            ("Line", vec![Debug, Clone]),
+            ("Error", vec![Debug]),
+        ] {
+            map.declare_external(External::new(Path::new(name).unwrap(), derives)).unwrap();
+        }
+        assert!(map.validate().is_ok());
+        assert!(map.analysis().is_defaultable(&id("Lines")));
+        // the item's own answer agrees with the analysis
+        assert!(map.get(&id("Lines")).unwrap().is_defaultable(&map));
+        assert_eq!(map.analysis().derivable(&id("Order")), vec![Debug]);
+        let status = Path::new("Status").unwrap();
+        assert!(map.declare_external(External::new(status, vec![])).is_err());
+
+        let datetime = Path::new("chrono::DateTime").unwrap().with_args(vec![named("Utc")]);
+        map.declare_external(External::new(datetime.clone(), vec![Clone])).unwrap();
+        assert!(map.get_external(&datetime).is_some());
+        assert!(map.get_external(&Path::new("chrono::DateTime").unwrap()).is_none());
+
+        // a `Path` derives through the external declared with exactly that path
+        let mut map = ItemMap::build(vec![
+            strukt("Event", vec![("at", Type::Path(datetime.clone()))]),
+        ]).unwrap();
+        assert_eq!(map.analysis().derivable(&id("Event")), vec![]);
+        let local = Path::new("chrono::DateTime").unwrap().with_args(vec![named("Local")]);
+        map.declare_external(External::new(local, vec![Debug, Clone])).unwrap();
+        assert_eq!(map.analysis().derivable(&id("Event")), vec![]);
+        map.declare_external(External::new(datetime, vec![Debug, Clone])).unwrap();
+        assert_eq!(map.analysis().derivable(&id("Event")), vec![Debug, Clone]);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let string = || Type::Primitive(Primitive::String);
+        let items = || {
+            vec![
+                strukt("Address", vec![("street", string()), ("city", string())]),
+                strukt(
+                    "Order",
+                    vec![
+                        ("shipping", named("ShippingAddress")),
+                        ("billing", named("BillingAddress1").optional(true)),
+                        ("lines", named("LineList1")),
+                    ],
+                ),
+                strukt("ShippingAddress", vec![("street", string()), ("city", string())]),
+                strukt("BillingAddress1", vec![("street", string()), ("city", string())]),
+                strukt("Point", vec![("street", string()), ("town", string())]),
+                strukt("LineList1", vec![("next", Type::Box(Box::new(named("LineList1"))))]),
+                strukt("LineList2", vec![("next", Type::Box(Box::new(named("LineList2"))))]),
+                strukt("Ping", vec![("other", Type::Box(Box::new(named("Pong"))))]),
+                strukt("Pong", vec![("other", Type::Box(Box::new(named("Ping"))))]),
+            ]
+        };
+        let mut map = ItemMap::build(items()).unwrap();
+        let merged: Vec<(&str, &str)> = vec![
+            ("BillingAddress1", "Address"),
+            ("LineList2", "LineList1"),
+            ("Pong", "Ping"),
+            ("ShippingAddress", "Address"),
+        ];
+        let ids = |pairs: &[(&str, &str)]| -> BTreeMap<Id, Id> {
+            pairs.iter().map(|&(from, to)| (id(from), id(to))).collect()
+        };
+        assert_eq!(map.dedup(false), ids(&merged));
+        let names: Vec<&str> = map.insertion_order().iter().map(|id| &**id).collect();
+        assert_eq!(names, vec!["Address", "Order", "Point", "LineList1", "Ping"]);
+        let order_refs: Vec<&str> = map.get(&id("Order"))
+            .unwrap()
+            .get_named_types()
+            .into_iter()
+            .map(|id| &**id)
+            .collect();
+        assert_eq!(order_refs, vec!["Address", "Address", "LineList1"]);
+        let ping_refs = map.get(&id("Ping")).unwrap().get_named_types();
+        assert_eq!(ping_refs, vec![&id("Ping")]);
+        assert!(map.dedup(false).is_empty());
+
+        let mut map = ItemMap::build(items()).unwrap();
+        assert_eq!(map.dedup(true), ids(&merged));
+        assert_eq!(map.insertion_order().len(), 9);
+        assert_eq!(
+            map.get(&id("ShippingAddress")).unwrap().to_string(),
+            "pub type ShippingAddress = Address;"
+        );
+        assert!(map.unresolved().is_empty());
+    }
+
+    #[test]
+    fn test_large_graph() {
+        // a long chain feeding into a single large cycle
+        let len = 5000;
+        let mut items = Vec::new();
+        for ix in 0..len {
+            let next = format!("Chain{}", ix + 1);
+            items.push(strukt(&format!("Chain{}", ix), vec![("next", named(&next))]));
+        }
+        for ix in 0..len {
+            let next = format!("Ring{}", (ix + 1) % len);
+            items.push(strukt(&format!("Ring{}", ix), vec![("next", named(&next))]));
+        }
+        items.push(strukt(&format!("Chain{}", len), vec![("ring", named("Ring0"))]));
+        let map = ItemMap::build(items).unwrap();
+        let analysis = map.analysis();
+        assert!(!analysis.is_recursive(&id("Chain0")));
+        assert!(analysis.is_recursive(&id("Ring0")));
+        assert!(analysis.contains_unboxed_id(&id("Chain0"), &id("Ring2500")));
+        assert!(!analysis.contains_unboxed_id(&id("Ring0"), &id("Chain0")));
+        for ix in 0..len {
+            let ring = id(&format!("Ring{}", ix));
+            assert!(analysis.contains_unboxed_id(&ring, &id("Ring0")));
+            assert!(!analysis.contains_unboxed_id(&ring, &id(&format!("Chain{}", ix))));
+        }
+        assert!(!analysis.is_defaultable(&id("Chain0")));
+    }
+}
@@ -0,0 +1,296 @@
+//! JSON Schema (draft 2020-12) exporter
+//!
+//! Every type item becomes an entry in `$defs`, describing the JSON that
+//! `serde` produces for it. Field and variant renames are honoured, fields
+//! that are `Option` or `#[serde(default)]` are not required, and `Option`
+//! additionally admits `null`. Enums follow their `serde` representation
+//! (externally, internally or adjacently tagged, or untagged).
+//! `Const` items have no JSON representation and are skipped.
+
+use serde_json::{Map, Value};
+
+use emit::Tagging;
+use errors::*;
+use {Enum, Id, ItemKind, ItemMap, Struct, Type};
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Build a schema document for `map`. If `root` is given, the
+/// document itself validates that item.
+pub fn json_schema(map: &ItemMap, root: Option<&Id>) -> Result<Value> {
+    let mut defs = Map::new();
+    for (id, item) in map.iter() {
+        let schema = match item.kind() {
+            ItemKind::Struct(s) => struct_schema(s),
+            ItemKind::Enum(e) => enum_schema(e),
+            ItemKind::NewType(n) => type_schema(&n.typ),
+            ItemKind::Alias(a) => type_schema(&a.typ),
+            ItemKind::Const(_) => continue,
+        };
+        defs.insert(id.unraw().to_string(), schema);
+    }
+    let mut doc = Map::new();
+    doc.insert("$schema".into(), DRAFT.into());
+    if let Some(root) = root {
+        if !defs.contains_key(root.unraw()) {
+            bail!("Root type '{}' not found", root)
+        }
+        doc.insert("$ref".into(), pointer(root).into());
+    }
+    doc.insert("$defs".into(), Value::Object(defs));
+    Ok(Value::Object(doc))
+}
+
+fn struct_schema(s: &Struct) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    let rename_all = s.attrs.rename_all();
+    for field in &s.fields {
+        let name = field.serde_name(rename_all).into_owned();
+        properties.insert(name.clone(), type_schema(&field.typ));
+        let optional = match field.typ {
+            Type::Option(_) => true,
+            _ => field.is_serde_default(),
+        };
+        if !optional {
+            required.push(Value::from(name));
+        }
+    }
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+fn enum_schema(e: &Enum) -> Value {
+    let tagging = Tagging::of(e);
+    let rename_all = e.attrs.rename_all();
+    if tagging == Tagging::External && e.variants.iter().all(|v| v.typ.is_none()) {
+        let names: Vec<_> = e.variants.iter().map(|v| v.serde_name(rename_all)).collect();
+        return json!({ "type": "string", "enum": names });
+    }
+    let variants: Vec<Value> = e.variants
+        .iter()
+        .map(|v| {
+            let name = v.serde_name(rename_all);
+            let name = &*name;
+            match (tagging, v.typ.as_ref()) {
+                (Tagging::External, None) => json!({ "const": name }),
+                (Tagging::External, Some(typ)) => object(&[(name, type_schema(typ))]),
+                (Tagging::Internal(tag), None) => tag_schema(tag, name),
+                (Tagging::Internal(tag), Some(typ)) => {
+                    json!({ "allOf": [type_schema(typ), tag_schema(tag, name)] })
+                }
+                (Tagging::Adjacent(tag, _), None) => object(&[(tag, json!({ "const": name }))]),
+                (Tagging::Adjacent(tag, content), Some(typ)) => {
+                    object(&[(tag, json!({ "const": name })), (content, type_schema(typ))])
+                }
+                (Tagging::Untagged, None) => json!({ "type": "null" }),
+                (Tagging::Untagged, Some(typ)) => type_schema(typ),
+            }
+        })
+        .collect();
+    if tagging == Tagging::Untagged {
+        json!({ "anyOf": variants })
+    } else {
+        json!({ "oneOf": variants })
+    }
+}
+
+/// An object with exactly the given (required) properties
+fn object(props: &[(&str, Value)]) -> Value {
+    let mut properties = Map::new();
+    for &(name, ref schema) in props {
+        properties.insert(name.into(), schema.clone());
+    }
+    let required: Vec<&str> = props.iter().map(|&(name, _)| name).collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// An object carrying an internal tag, open to the fields of the variant
+fn tag_schema(tag: &str, name: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": { tag: { "const": name } },
+        "required": [tag],
+    })
+}
+
+fn type_schema(typ: &Type) -> Value {
+    use Primitive::*;
+    match *typ {
+        Type::Primitive(p) => {
+            match p {
+                Null => json!({ "type": "null" }),
+                Boolean => json!({ "type": "boolean" }),
+                I8 | I16 | I32 | I64 => json!({ "type": "integer" }),
+                U8 | U16 | U32 => json!({ "type": "integer", "minimum": 0 }),
+                F32 | F64 => json!({ "type": "number" }),
+                String | Str => json!({ "type": "string" }),
+            }
+        }
+        Type::Box(ref inner) |
+        Type::Ref(_, ref inner) => type_schema(inner),
+        Type::Vec(ref inner) => json!({ "type": "array", "items": type_schema(inner) }),
+        Type::Option(ref inner) => json!({ "anyOf": [type_schema(inner), { "type": "null" }] }),
+        Type::Map(_, ref value) => {
+            json!({ "type": "object", "additionalProperties": type_schema(value) })
+        }
+        Type::Result(ref ok, ref err) => {
+            json!({
+                "oneOf": [
+                    object(&[("Ok", type_schema(ok))]),
+                    object(&[("Err", type_schema(err))]),
+                ]
+            })
+        }
+        Type::Tuple(ref elems) => {
+            let elems: Vec<Value> = elems.iter().map(type_schema).collect();
+            json!({
+                "type": "array",
+                "prefixItems": elems,
+                "minItems": elems.len(),
+                "items": false,
+            })
+        }
+        Type::Named(ref id) => reference(id),
+        // nothing is known about the shape of an external type
+        Type::Path(_) => json!({}),
+    }
+}
+
+fn reference(id: &Id) -> Value {
+    json!({ "$ref": pointer(id) })
+}
+
+fn pointer(id: &Id) -> String {
+    format!("#/$defs/{}", id.unraw())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Attributes, Const, Field, FieldAttr, Item, Primitive, Serde, TypeId, ValueId, Variant,
+         Visibility};
+
+    #[test]
+    fn test_json_schema() {
+        let order = Struct::new(
+            TypeId::new("Order").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![
+                Field::with_rename("orderId", Type::Primitive(Primitive::U32)).unwrap(),
+                Field::new(
+                    ValueId::new("notes").unwrap(),
+                    Type::Option(Box::new(Type::Primitive(Primitive::String))),
+                    vec![],
+                ),
+                Field::new(
+                    ValueId::new("lines").unwrap(),
+                    Type::Vec(Box::new(Type::named("Line").unwrap())),
+                    vec![FieldAttr::SerdeDefault],
+                ),
+                Field::new(
+                    ValueId::new("status").unwrap(),
+                    Type::named("Status").unwrap(),
+                    vec![],
+                ),
+            ],
+        ).unwrap();
+        let status = Enum::new(
+            TypeId::new("Status").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![
+                Variant::new(
+                    TypeId::new("InProgress").unwrap(),
+                    None,
+                    vec![FieldAttr::SerdeRename("in-progress".into())],
+                ),
+                Variant::new(TypeId::new("Done").unwrap(), None, vec![]),
+            ],
+        ).unwrap();
+        let line = Enum::new(
+            TypeId::new("Line").unwrap(),
+            Visibility::Public,
+            Attributes::default().serde(&[Serde::Tag("kind".into())]),
+            vec![
+                Variant::new(TypeId::new("Empty").unwrap(), None, vec![]),
+                Variant::new(
+                    TypeId::new("Item").unwrap(),
+                    Some(Type::named("Sku").unwrap()),
+                    vec![],
+                ),
+            ],
+        ).unwrap();
+        let sku = ::Alias::new(
+            TypeId::new("Sku").unwrap(),
+            Visibility::Public,
+            Type::Map(
+                Box::new(Type::Primitive(Primitive::String)),
+                Box::new(Type::Primitive(Primitive::F64)),
+            ),
+        );
+        let max = Const::new(
+            ValueId::constant("MAX").unwrap(),
+            Visibility::Public,
+            Type::Primitive(Primitive::I64),
+            "1".into(),
+        );
+        let items: Vec<Box<Item>> = vec![
+            Box::new(order),
+            Box::new(status),
+            Box::new(line),
+            Box::new(sku),
+            Box::new(max),
+        ];
+        let map = ItemMap::build(items).unwrap();
+        let schema = json_schema(&map, Some(&Id::new("Order").unwrap())).unwrap();
+        let expect = json!({
+            "$schema": DRAFT,
+            "$ref": "#/$defs/Order",
+            "$defs": {
+                "Order": {
+                    "type": "object",
+                    "properties": {
+                        "orderId": { "type": "integer", "minimum": 0 },
+                        "notes": { "anyOf": [{ "type": "string" }, { "type": "null" }] },
+                        "lines": { "type": "array", "items": { "$ref": "#/$defs/Line" } },
+                        "status": { "$ref": "#/$defs/Status" }
+                    },
+                    "required": ["orderId", "status"]
+                },
+                "Status": { "type": "string", "enum": ["in-progress", "Done"] },
+                "Line": {
+                    "oneOf": [
+                        {
+                            "type": "object",
+                            "properties": { "kind": { "const": "Empty" } },
+                            "required": ["kind"]
+                        },
+                        {
+                            "allOf": [
+                                { "$ref": "#/$defs/Sku" },
+                                {
+                                    "type": "object",
+                                    "properties": { "kind": { "const": "Item" } },
+                                    "required": ["kind"]
+                                }
+                            ]
+                        }
+                    ]
+                },
+                "Sku": { "type": "object", "additionalProperties": { "type": "number" } }
+            }
+        });
+        assert_eq!(schema, expect);
+        assert!(json_schema(&map, Some(&Id::new("MAX").unwrap())).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+//! Exporters which describe an `ItemMap` in other schema languages
+
+pub mod json_schema;
+pub mod proto;
+pub mod typescript;
+
+use {Enum, Serde};
+
+/// How `serde` represents an enum, as chosen by its container attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tagging<'a> {
+    External,
+    Internal(&'a str),
+    Adjacent(&'a str, &'a str),
+    Untagged,
+}
+
+impl<'a> Tagging<'a> {
+    pub(crate) fn of(e: &'a Enum) -> Tagging<'a> {
+        let mut tag = None;
+        let mut content = None;
+        for attr in &e.attrs.serde {
+            match *attr {
+                Serde::Untagged => return Tagging::Untagged,
+                Serde::Tag(ref t) => tag = Some(&**t),
+                Serde::Content(ref c) => content = Some(&**c),
+                Serde::RenameAll(_) | Serde::Custom(_) => (),
+            }
+        }
+        match (tag, content) {
+            (Some(tag), Some(content)) => Tagging::Adjacent(tag, content),
+            (Some(tag), None) => Tagging::Internal(tag),
+            _ => Tagging::External,
+        }
+    }
+}
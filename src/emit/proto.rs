@@ -0,0 +1,518 @@
+//! Protocol Buffers (proto3) exporter
+//!
+//! Structs become messages, enums of unit variants become proto enums and
+//! other enums become a message wrapping a `oneof`. `Alias` and `NewType`
+//! items are resolved to the type they wrap, since proto has no equivalent.
+//! `Const` items are skipped.
+//!
+//! Field numbers are recorded in a `ProtoLock`, which should be persisted
+//! alongside the generated file. Numbers are never reassigned: fields keep
+//! their number across runs and the numbers of removed fields are `reserved`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use inflector::Inflector;
+use serde_json::{self, Map, Value};
+
+use errors::*;
+use {Case, Enum, Field, Id, Item, ItemKind, ItemMap, Primitive, Struct, Type};
+
+/// Proto reserves these numbers for its own use
+const RESERVED_RANGE: (u32, u32) = (19000, 19999);
+
+/// Persistent record of the field numbers handed out to each message and enum
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtoLock(BTreeMap<String, LockEntry>);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct LockEntry {
+    /// Next unused number, so removed fields are never recycled
+    next: u32,
+    fields: BTreeMap<String, u32>,
+}
+
+impl ProtoLock {
+    /// Load a lock file, or start afresh if it does not exist yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ProtoLock> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(ProtoLock::default());
+        }
+        let text = fs::read_to_string(path).chain_err(
+            || format!("Failed to read {}", path.display()),
+        )?;
+        ProtoLock::from_json(&text).chain_err(|| format!("Invalid lock file {}", path.display()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json()).chain_err(
+            || format!("Failed to write {}", path.display()),
+        )
+    }
+
+    pub fn from_json(json: &str) -> Result<ProtoLock> {
+        let value: Value = serde_json::from_str(json)?;
+        let mut lock = ProtoLock::default();
+        let entries = match value {
+            Value::Object(entries) => entries,
+            _ => bail!("Expected an object"),
+        };
+        for (name, entry) in entries {
+            let next = entry.get("next").and_then(Value::as_u64);
+            let fields = entry.get("fields").and_then(Value::as_object);
+            let (next, fields) = match (next, fields) {
+                (Some(next), Some(fields)) => (next as u32, fields),
+                _ => bail!("Malformed entry for '{}'", name),
+            };
+            let mut entry = LockEntry {
+                next,
+                fields: BTreeMap::new(),
+            };
+            for (field, number) in fields {
+                match number.as_u64() {
+                    Some(number) => entry.fields.insert(field.clone(), number as u32),
+                    None => bail!("Malformed number for '{}.{}'", name, field),
+                };
+            }
+            lock.0.insert(name, entry);
+        }
+        Ok(lock)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut entries = Map::new();
+        for (name, entry) in &self.0 {
+            let fields: Map<String, Value> = entry
+                .fields
+                .iter()
+                .map(|(field, &number)| (field.clone(), number.into()))
+                .collect();
+            entries.insert(name.clone(), json!({ "next": entry.next, "fields": fields }));
+        }
+        let mut json = serde_json::to_string_pretty(&Value::Object(entries)).unwrap();
+        json.push('\n');
+        json
+    }
+
+    /// The number of `field` within `scope`, allocating one if necessary
+    fn number(&mut self, scope: &str, field: &str) -> u32 {
+        let entry = self.0.entry(scope.into()).or_insert_with(|| {
+            LockEntry {
+                next: 1,
+                fields: BTreeMap::new(),
+            }
+        });
+        if let Some(&number) = entry.fields.get(field) {
+            return number;
+        }
+        if entry.next >= RESERVED_RANGE.0 && entry.next <= RESERVED_RANGE.1 {
+            entry.next = RESERVED_RANGE.1 + 1;
+        }
+        let number = entry.next;
+        entry.next += 1;
+        entry.fields.insert(field.into(), number);
+        number
+    }
+
+    /// Previously allocated fields of `scope` which are no longer in use
+    fn retired(&self, scope: &str, live: &[String]) -> Vec<(&str, u32)> {
+        self.0
+            .get(scope)
+            .map(|entry| {
+                entry
+                    .fields
+                    .iter()
+                    .filter(|&(name, _)| !live.contains(name))
+                    .map(|(name, &number)| (&**name, number))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Render `map` as a proto3 file, numbering fields according to `lock`
+/// and recording any newly allocated numbers in it
+pub fn proto(map: &ItemMap, package: Option<&str>, lock: &mut ProtoLock) -> Result<String> {
+    let mut emitter = Emitter {
+        map,
+        lock,
+        uses_empty: false,
+    };
+    let mut defs = Vec::new();
+    for (_, item) in map.iter() {
+        match item.kind() {
+            ItemKind::Struct(s) => defs.push(emitter.message(s)?),
+            ItemKind::Enum(e) => {
+                if e.variants.iter().all(|v| v.typ.is_none()) {
+                    defs.push(emitter.enumeration(e))
+                } else {
+                    defs.push(emitter.oneof(e)?)
+                }
+            }
+            _ => (),
+        }
+    }
+    let mut out = String::from("syntax = \"proto3\";\n\n");
+    if let Some(package) = package {
+        out.push_str(&format!("package {};\n\n", package));
+    }
+    if emitter.uses_empty {
+        out.push_str("import \"google/protobuf/empty.proto\";\n\n");
+    }
+    out.push_str(&defs.join("\n"));
+    Ok(out)
+}
+
+struct Emitter<'a> {
+    map: &'a ItemMap,
+    lock: &'a mut ProtoLock,
+    uses_empty: bool,
+}
+
+impl<'a> Emitter<'a> {
+    fn message(&mut self, s: &Struct) -> Result<String> {
+        let mut body = String::new();
+        let mut live = Vec::new();
+        for field in &s.fields {
+            let name = field.name.unraw().to_string();
+            let label = self.field_type(&field.typ)
+                .chain_err(|| format!("Field '{}.{}'", s.name, field.name))?;
+            let number = self.lock.number(&s.name, &name);
+            let json_name = json_name(field, s.attrs.rename_all());
+            body.push_str(&format!("  {} {} = {}{};\n", label, name, number, json_name));
+            live.push(name);
+        }
+        body.push_str(&self.reserved(&s.name, &live));
+        Ok(format!("message {} {{\n{}}}\n", s.name.unraw(), body))
+    }
+
+    fn enumeration(&mut self, e: &Enum) -> String {
+        let prefix = e.name.to_screaming_snake_case();
+        let mut body = format!("  {}_UNSPECIFIED = 0;\n", prefix);
+        let mut live = Vec::new();
+        for variant in &e.variants {
+            let name = format!("{}_{}", prefix, variant.name.unraw().to_screaming_snake_case());
+            let number = self.lock.number(&e.name, &name);
+            body.push_str(&format!("  {} = {};\n", name, number));
+            live.push(name);
+        }
+        body.push_str(&self.reserved(&e.name, &live));
+        format!("enum {} {{\n{}}}\n", e.name.unraw(), body)
+    }
+
+    fn oneof(&mut self, e: &Enum) -> Result<String> {
+        let mut body = format!("  oneof {} {{\n", e.name.to_snake_case());
+        let mut live = Vec::new();
+        for variant in &e.variants {
+            let name = variant.name.unraw().to_snake_case();
+            let typ = match variant.typ {
+                Some(ref typ) => {
+                    match self.field_type(typ) {
+                        Ok(ref t) if t.starts_with("repeated ") || t.starts_with("map<") ||
+                                     t.starts_with("optional ") => {
+                            bail!(
+                                "Variant '{}::{}' cannot be a repeated, map or optional field",
+                                e.name,
+                                variant.name
+                            )
+                        }
+                        other => {
+                            other.chain_err(|| format!("Variant '{}::{}'", e.name, variant.name))?
+                        }
+                    }
+                }
+                None => {
+                    self.uses_empty = true;
+                    "google.protobuf.Empty".into()
+                }
+            };
+            let number = self.lock.number(&e.name, &name);
+            body.push_str(&format!("    {} {} = {};\n", typ, name, number));
+            live.push(name);
+        }
+        body.push_str("  }\n");
+        body.push_str(&self.reserved(&e.name, &live));
+        Ok(format!("message {} {{\n{}}}\n", e.name.unraw(), body))
+    }
+
+    fn reserved(&self, scope: &str, live: &[String]) -> String {
+        let retired = self.lock.retired(scope, live);
+        if retired.is_empty() {
+            return String::new();
+        }
+        let numbers: Vec<String> = retired.iter().map(|&(_, n)| n.to_string()).collect();
+        let names: Vec<String> = retired.iter().map(|&(name, _)| format!("{:?}", name)).collect();
+        format!(
+            "  reserved {};\n  reserved {};\n",
+            numbers.join(", "),
+            names.join(", ")
+        )
+    }
+
+    /// The type of a field, including any `optional`/`repeated` label
+    fn field_type(&mut self, typ: &Type) -> Result<String> {
+        let typ = self.resolve(typ)?;
+        let label = match *typ {
+            Type::Option(ref inner) => {
+                match *self.resolve(inner)? {
+                    Type::Vec(_) | Type::Map(_, _) => self.field_type(inner)?,
+                    ref other => format!("optional {}", self.scalar(other)?),
+                }
+            }
+            Type::Vec(ref inner) => {
+                match *self.resolve(inner)? {
+                    Type::Primitive(Primitive::U8) => "bytes".into(),
+                    ref other => format!("repeated {}", self.scalar(other)?),
+                }
+            }
+            Type::Map(ref key, ref value) => {
+                let key = match *self.resolve(key)? {
+                    Type::Primitive(Primitive::F32) |
+                    Type::Primitive(Primitive::F64) => bail!("Map keys cannot be floats"),
+                    Type::Primitive(_) => self.scalar(key)?,
+                    _ => bail!("Map keys must be integers, strings or bools"),
+                };
+                format!("map<{}, {}>", key, self.scalar(value)?)
+            }
+            ref other => self.scalar(other)?,
+        };
+        Ok(label)
+    }
+
+    /// The type of a singular field
+    fn scalar(&mut self, typ: &Type) -> Result<String> {
+        use Primitive::*;
+        let scalar = match *self.resolve(typ)? {
+            Type::Primitive(p) => {
+                match p {
+                    Boolean => "bool",
+                    I8 | I16 | I32 => "int32",
+                    I64 => "int64",
+                    U8 | U16 | U32 => "uint32",
+                    F32 => "float",
+                    F64 => "double",
+                    String | Str => "string",
+                    Null => {
+                        self.uses_empty = true;
+                        "google.protobuf.Empty"
+                    }
+                }.to_string()
+            }
+            Type::Named(ref id) => id.unraw().to_string(),
+            Type::Vec(ref inner) if *self.resolve(inner)? == Type::Primitive(U8) => "bytes".into(),
+            ref other => bail!("Type '{}' cannot be nested in proto", other),
+        };
+        Ok(scalar)
+    }
+
+    /// Strip boxes and references, and look through aliases and newtypes
+    fn resolve<'t>(&self, typ: &'t Type) -> Result<&'t Type>
+    where
+        'a: 't,
+    {
+        self.resolve_inner(typ, &mut Vec::new())
+    }
+
+    fn resolve_inner<'t>(&self, typ: &'t Type, seen: &mut Vec<&'t Id>) -> Result<&'t Type>
+    where
+        'a: 't,
+    {
+        match *typ {
+            Type::Box(ref inner) |
+            Type::Ref(_, ref inner) => self.resolve_inner(inner, seen),
+            Type::Result(_, _) => bail!("Result has no proto equivalent"),
+            Type::Tuple(_) => bail!("Tuple has no proto equivalent"),
+            Type::Path(ref path) => bail!("External type '{}' has no proto equivalent", path),
+            Type::Named(ref id) => {
+                if seen.contains(&id) {
+                    bail!("Alias '{}' refers to itself", id)
+                }
+                let item: &'a dyn Item = match self.map.get(id) {
+                    Some(item) => &**item,
+                    None => bail!("Unknown type '{}'", id),
+                };
+                match item.kind() {
+                    ItemKind::Alias(a) => {
+                        seen.push(id);
+                        self.resolve_inner(&a.typ, seen)
+                    }
+                    ItemKind::NewType(n) => {
+                        seen.push(id);
+                        self.resolve_inner(&n.typ, seen)
+                    }
+                    ItemKind::Const(_) => bail!("'{}' is not a type", id),
+                    _ => Ok(typ),
+                }
+            }
+            _ => Ok(typ),
+        }
+    }
+}
+
+fn json_name(field: &Field, rename_all: Option<Case>) -> String {
+    let serde_name = field.serde_name(rename_all);
+    if serde_name == field.name.unraw() {
+        String::new()
+    } else {
+        format!(" [json_name = {:?}]", serde_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Alias, Attributes, Item, TypeId, ValueId, Variant, Visibility};
+
+    fn order(fields: Vec<Field>) -> Box<Item> {
+        Box::new(Struct::new(
+            TypeId::new("Order").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            fields,
+        ).unwrap())
+    }
+
+    #[test]
+    fn test_proto() {
+        let fields = vec![
+            Field::with_rename("orderId", Type::named("OrderId").unwrap()).unwrap(),
+            Field::new(
+                ValueId::new("notes").unwrap(),
+                Type::Option(Box::new(Type::Primitive(Primitive::String))),
+                vec![],
+            ),
+            Field::new(
+                ValueId::new("lines").unwrap(),
+                Type::Vec(Box::new(Type::named("Line").unwrap())),
+                vec![],
+            ),
+            Field::new(
+                ValueId::new("status").unwrap(),
+                Type::named("Status").unwrap(),
+                vec![],
+            ),
+        ];
+        let status = Enum::new(
+            TypeId::new("Status").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![
+                Variant::new(TypeId::new("InProgress").unwrap(), None, vec![]),
+                Variant::new(TypeId::new("Done").unwrap(), None, vec![]),
+            ],
+        ).unwrap();
+        let line = Enum::new(
+            TypeId::new("Line").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![
+                Variant::new(TypeId::new("Empty").unwrap(), None, vec![]),
+                Variant::new(
+                    TypeId::new("Sku").unwrap(),
+                    Some(Type::Vec(Box::new(Type::Primitive(Primitive::U8)))),
+                    vec![],
+                ),
+            ],
+        ).unwrap();
+        let order_id = Alias::new(
+            TypeId::new("OrderId").unwrap(),
+            Visibility::Public,
+            Type::Primitive(Primitive::I64),
+        );
+        let items: Vec<Box<Item>> = vec![
+            order(fields.clone()),
+            Box::new(status.clone()),
+            Box::new(line),
+            Box::new(order_id.clone()),
+        ];
+        let map = ItemMap::build(items).unwrap();
+        let mut lock = ProtoLock::default();
+        let out = proto(&map, Some("shop.v1"), &mut lock).unwrap();
+        let expect = r#"syntax = "proto3";
+
+package shop.v1;
+
+import "google/protobuf/empty.proto";
+
+message Line {
+  oneof line {
+    google.protobuf.Empty empty = 1;
+    bytes sku = 2;
+  }
+}
+
+message Order {
+  int64 order_id = 1 [json_name = "orderId"];
+  optional string notes = 2;
+  repeated Line lines = 3;
+  Status status = 4;
+}
+
+enum Status {
+  STATUS_UNSPECIFIED = 0;
+  STATUS_IN_PROGRESS = 1;
+  STATUS_DONE = 2;
+}
+"#;
+        assert_eq!(out, expect);
+
+        // removing and adding fields never renumbers existing ones
+        let lock = ProtoLock::from_json(&lock.to_json()).unwrap();
+        let fields = vec![
+            fields[3].clone(),
+            Field::new(
+                ValueId::new("total").unwrap(),
+                Type::Primitive(Primitive::F64),
+                vec![],
+            ),
+            fields[0].clone(),
+        ];
+        let items: Vec<Box<Item>> = vec![order(fields), Box::new(status), Box::new(order_id)];
+        let map = ItemMap::build(items).unwrap();
+        let mut relock = lock.clone();
+        let out = proto(&map, None, &mut relock).unwrap();
+        let expect = r#"syntax = "proto3";
+
+message Order {
+  Status status = 4;
+  double total = 5;
+  int64 order_id = 1 [json_name = "orderId"];
+  reserved 3, 2;
+  reserved "lines", "notes";
+}
+
+enum Status {
+  STATUS_UNSPECIFIED = 0;
+  STATUS_IN_PROGRESS = 1;
+  STATUS_DONE = 2;
+}
+"#;
+        assert_eq!(out, expect);
+        assert_ne!(lock, relock);
+    }
+
+    #[test]
+    fn test_proto_errors() {
+        let fields = vec![
+            Field::new(
+                ValueId::new("nested").unwrap(),
+                Type::Vec(Box::new(Type::Vec(Box::new(Type::Primitive(Primitive::I32))))),
+                vec![],
+            ),
+        ];
+        let map = ItemMap::build(vec![order(fields)]).unwrap();
+        assert!(proto(&map, None, &mut ProtoLock::default()).is_err());
+        let fields = vec![
+            Field::new(
+                ValueId::new("missing").unwrap(),
+                Type::named("Missing").unwrap(),
+                vec![],
+            ),
+        ];
+        let map = ItemMap::build(vec![order(fields)]).unwrap();
+        assert!(proto(&map, None, &mut ProtoLock::default()).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! TypeScript declaration exporter
+//!
+//! Generates `.d.ts` declarations matching the JSON that `serde` produces:
+//! structs become interfaces, enums of unit variants become string-literal
+//! unions and other enums become (discriminated) unions following their
+//! `serde` representation. Field and variant renames are honoured, `Option`
+//! becomes `T | null`, and fields that are `Option` or `#[serde(default)]`
+//! are marked optional. `Const` items are skipped.
+
+use serde_json;
+
+use emit::Tagging;
+use {Enum, ItemKind, ItemMap, Struct, Type};
+
+/// Render every type item of `map` as a TypeScript declaration
+pub fn typescript(map: &ItemMap) -> String {
+    let decls: Vec<String> = map.iter()
+        .filter_map(|(id, item)| {
+            let name = id.unraw();
+            match item.kind() {
+                ItemKind::Struct(s) => Some(interface(s)),
+                ItemKind::Enum(e) => Some(format!("export type {} = {};\n", name, union(e))),
+                ItemKind::NewType(n) => {
+                    Some(format!("export type {} = {};\n", name, ts_type(&n.typ)))
+                }
+                ItemKind::Alias(a) => {
+                    Some(format!("export type {} = {};\n", name, ts_type(&a.typ)))
+                }
+                ItemKind::Const(_) => None,
+            }
+        })
+        .collect();
+    decls.join("\n")
+}
+
+fn interface(s: &Struct) -> String {
+    let mut out = format!("export interface {} {{\n", s.name.unraw());
+    let rename_all = s.attrs.rename_all();
+    for field in &s.fields {
+        let optional = match field.typ {
+            Type::Option(_) => true,
+            _ => field.is_serde_default(),
+        };
+        out.push_str(&format!(
+            "  {}{}: {};\n",
+            property(&field.serde_name(rename_all)),
+            if optional { "?" } else { "" },
+            ts_type(&field.typ)
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn union(e: &Enum) -> String {
+    if e.variants.is_empty() {
+        return "never".into();
+    }
+    let tagging = Tagging::of(e);
+    let rename_all = e.attrs.rename_all();
+    let variants: Vec<String> = e.variants
+        .iter()
+        .map(|v| {
+            let serde_name = v.serde_name(rename_all);
+            let name = literal(&serde_name);
+            match (tagging, v.typ.as_ref()) {
+                (Tagging::External, None) => name,
+                (Tagging::External, Some(typ)) => {
+                    format!("{{ {}: {} }}", property(&serde_name), ts_type(typ))
+                }
+                (Tagging::Internal(tag), None) |
+                (Tagging::Adjacent(tag, _), None) => format!("{{ {}: {} }}", property(tag), name),
+                (Tagging::Internal(tag), Some(typ)) => {
+                    format!("({{ {}: {} }} & {})", property(tag), name, ts_type(typ))
+                }
+                (Tagging::Adjacent(tag, content), Some(typ)) => {
+                    format!(
+                        "{{ {}: {}; {}: {} }}",
+                        property(tag),
+                        name,
+                        property(content),
+                        ts_type(typ)
+                    )
+                }
+                (Tagging::Untagged, None) => "null".into(),
+                (Tagging::Untagged, Some(typ)) => ts_type(typ),
+            }
+        })
+        .collect();
+    variants.join(" | ")
+}
+
+fn ts_type(typ: &Type) -> String {
+    use Primitive::*;
+    match *typ {
+        Type::Primitive(p) => {
+            match p {
+                Null => "null",
+                Boolean => "boolean",
+                I8 | I16 | I32 | I64 | U8 | U16 | U32 | F32 | F64 => "number",
+                String | Str => "string",
+            }.into()
+        }
+        Type::Box(ref inner) |
+        Type::Ref(_, ref inner) => ts_type(inner),
+        Type::Vec(ref inner) => {
+            match **inner {
+                Type::Option(_) | Type::Result(_, _) => format!("({})[]", ts_type(inner)),
+                _ => format!("{}[]", ts_type(inner)),
+            }
+        }
+        Type::Option(ref inner) => format!("{} | null", ts_type(inner)),
+        // serde_json always serializes map keys as strings
+        Type::Map(_, ref value) => format!("Record<string, {}>", ts_type(value)),
+        Type::Result(ref ok, ref err) => {
+            format!("{{ Ok: {} }} | {{ Err: {} }}", ts_type(ok), ts_type(err))
+        }
+        Type::Tuple(ref elems) => {
+            let elems: Vec<_> = elems.iter().map(ts_type).collect();
+            format!("[{}]", elems.join(", "))
+        }
+        Type::Named(ref id) => id.unraw().to_string(),
+        Type::Path(_) => "unknown".into(),
+    }
+}
+
+/// A property name, quoted if it is not a valid identifier
+fn property(name: &str) -> String {
+    let mut chars = name.chars();
+    let valid = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        }
+        _ => false,
+    };
+    if valid { name.into() } else { literal(name) }
+}
+
+fn literal(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Attributes, Field, FieldAttr, Item, Primitive, Serde, TypeId, ValueId, Variant,
+         Visibility};
+
+    #[test]
+    fn test_typescript() {
+        let order = Struct::new(
+            TypeId::new("Order").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![
+                Field::with_rename("orderId", Type::Primitive(Primitive::U32)).unwrap(),
+                Field::with_rename(
+                    "shipping-notes",
+                    Type::Option(Box::new(Type::Primitive(Primitive::String))),
+                ).unwrap(),
+                Field::new(
+                    ValueId::new("lines").unwrap(),
+                    Type::Vec(Box::new(Type::Option(Box::new(Type::named("Line").unwrap())))),
+                    vec![FieldAttr::SerdeDefault],
+                ),
+                Field::new(
+                    ValueId::new("totals").unwrap(),
+                    Type::Map(
+                        Box::new(Type::Primitive(Primitive::String)),
+                        Box::new(Type::Primitive(Primitive::F64)),
+                    ),
+                    vec![],
+                ),
+            ],
+        ).unwrap();
+        let status = Enum::new(
+            TypeId::new("Status").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![
+                Variant::new(
+                    TypeId::new("InProgress").unwrap(),
+                    None,
+                    vec![FieldAttr::SerdeRename("in-progress".into())],
+                ),
+                Variant::new(TypeId::new("Done").unwrap(), None, vec![]),
+            ],
+        ).unwrap();
+        let variant = |name: &str, typ: Option<Type>| {
+            Variant::new(TypeId::new(name).unwrap(), typ, vec![])
+        };
+        let enum_with = |name: &str, serde: &[Serde]| {
+            Enum::new(
+                TypeId::new(name).unwrap(),
+                Visibility::Public,
+                Attributes::default().serde(serde),
+                vec![
+                    variant("Empty", None),
+                    variant("Item", Some(Type::named("Order").unwrap())),
+                ],
+            ).unwrap()
+        };
+        let items: Vec<Box<Item>> = vec![
+            Box::new(order),
+            Box::new(status),
+            Box::new(enum_with("External", &[])),
+            Box::new(enum_with("Internal", &[Serde::Tag("kind".into())])),
+            Box::new(enum_with(
+                "Adjacent",
+                &[Serde::Tag("t".into()), Serde::Content("c".into())],
+            )),
+            Box::new(enum_with("Untagged", &[Serde::Untagged])),
+        ];
+        let map = ItemMap::build(items).unwrap();
+        let expect = r#"export type Adjacent = { t: "Empty" } | { t: "Item"; c: Order };
+
+export type External = "Empty" | { Item: Order };
+
+export type Internal = { kind: "Empty" } | ({ kind: "Item" } & Order);
+
+export interface Order {
+  orderId: number;
+  "shipping-notes"?: string | null;
+  lines?: (Line | null)[];
+  totals: Record<string, number>;
+}
+
+export type Status = "in-progress" | "Done";
+
+export type Untagged = null | Order;
+"#;
+        assert_eq!(typescript(&map), expect);
+    }
+}
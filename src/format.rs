@@ -0,0 +1,444 @@
+//! Formatting generated Rust source
+//!
+//! The input is always parsed first, so malformed code is reported as
+//! `ErrorKind::Syntax` whichever backend is used. Input which is already
+//! formatted is returned unchanged.
+
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use prettyplease;
+use syn;
+use syn::meta::ParseNestedMeta;
+use syn::visit::{self, Visit};
+
+use errors::*;
+use {Edition, Id};
+
+/// How source is formatted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// A pretty printer running in process, needing no external tools.
+    /// Its style is `prettyplease`'s rather than rustfmt's: lines are
+    /// wrapped at about 80 columns and lists are broken up differently.
+    #[default]
+    Builtin,
+    /// The `rustfmt` binary at the given path, or on the `PATH` if None,
+    /// fed over stdin
+    Rustfmt(Option<String>),
+}
+
+/// rustfmt's `trailing_comma`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingComma {
+    Always,
+    Never,
+    /// Only when the list is split over several lines (rustfmt's default)
+    Vertical,
+}
+
+/// rustfmt's `group_imports`. Unstable in rustfmt, so it is only
+/// applied by a nightly `rustfmt`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupImports {
+    Preserve,
+    StdExternalCrate,
+    One,
+}
+
+/// Style settings, so that generated files match the rest of a
+/// repository. Unset options are left to the backend, or to the
+/// `rustfmt.toml` given with `config_path`.
+///
+/// Only `Backend::Rustfmt` honours these: the builtin backend fails if
+/// any option is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    max_width: Option<usize>,
+    hard_tabs: Option<bool>,
+    trailing_comma: Option<TrailingComma>,
+    group_imports: Option<GroupImports>,
+    config_path: Option<String>,
+}
+
+impl FormatOptions {
+    pub fn max_width(mut self, width: usize) -> Self {
+        self.max_width = Some(width);
+        self
+    }
+
+    /// Indent with tabs rather than four spaces
+    pub fn hard_tabs(mut self, hard_tabs: bool) -> Self {
+        self.hard_tabs = Some(hard_tabs);
+        self
+    }
+
+    pub fn trailing_comma(mut self, trailing_comma: TrailingComma) -> Self {
+        self.trailing_comma = Some(trailing_comma);
+        self
+    }
+
+    pub fn group_imports(mut self, group_imports: GroupImports) -> Self {
+        self.group_imports = Some(group_imports);
+        self
+    }
+
+    /// A `rustfmt.toml`, or a directory to search for one. Options set
+    /// here take precedence over the file.
+    pub fn config_path<P: Into<String>>(mut self, path: P) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// The `--config` argument for rustfmt, if any options are set
+    fn rustfmt_config(&self) -> Option<String> {
+        let mut config = Vec::new();
+        if let Some(width) = self.max_width {
+            config.push(format!("max_width={}", width));
+        }
+        if let Some(hard_tabs) = self.hard_tabs {
+            config.push(format!("hard_tabs={}", hard_tabs));
+        }
+        if let Some(trailing_comma) = self.trailing_comma {
+            config.push(format!("trailing_comma={:?}", trailing_comma));
+        }
+        if let Some(group_imports) = self.group_imports {
+            config.push(format!("group_imports={:?}", group_imports));
+        }
+        if config.is_empty() {
+            None
+        } else {
+            Some(config.join(","))
+        }
+    }
+
+    /// Check that the builtin backend can honour these options
+    fn check_builtin(&self) -> Result<()> {
+        let unsupported = [
+            ("max_width", self.max_width.is_some()),
+            ("hard_tabs", self.hard_tabs.is_some()),
+            ("trailing_comma", self.trailing_comma.is_some()),
+            ("group_imports", self.group_imports.is_some()),
+            ("config_path", self.config_path.is_some()),
+        ];
+        for &(name, set) in &unsupported {
+            if set {
+                bail!("The builtin formatter does not support '{}', use Backend::Rustfmt", name)
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats Rust source with a chosen `Backend`
+///
+/// # Example
+///
+/// ```ignore
+/// let formatter = Formatter::default()
+///     .backend(Backend::Rustfmt(None))
+///     .options(FormatOptions::default().config_path("rustfmt.toml"));
+/// let pretty = formatter.format(&my_struct.to_string())?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Formatter {
+    backend: Backend,
+    edition: Edition,
+    options: FormatOptions,
+}
+
+impl Formatter {
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// The edition passed to `rustfmt`
+    pub fn edition(mut self, edition: Edition) -> Self {
+        self.edition = edition;
+        self
+    }
+
+    pub fn options(mut self, options: FormatOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn format(&self, code: &str) -> Result<String> {
+        let file = parse(code)?;
+        match self.backend {
+            Backend::Builtin => {
+                self.options.check_builtin()?;
+                Ok(prettyplease::unparse(&file))
+            }
+            Backend::Rustfmt(ref path) => {
+                let binary = path.as_ref().map(|p| &**p).unwrap_or("rustfmt");
+                self.rustfmt(binary, code)
+            }
+        }
+    }
+
+    fn rustfmt(&self, binary: &str, code: &str) -> Result<String> {
+        let mut command = Command::new(binary);
+        command.arg("--emit").arg("stdout").arg("--edition").arg(edition_flag(self.edition));
+        if let Some(ref path) = self.options.config_path {
+            command.arg("--config-path").arg(path);
+        }
+        if let Some(config) = self.options.rustfmt_config() {
+            command.arg("--config").arg(config);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .chain_err(|| format!("Failed to run '{}'", binary))?;
+        // written from another thread, so that a large output filling
+        // the stdout pipe cannot block rustfmt while we are still writing
+        let mut stdin = child.stdin.take().unwrap();
+        let input = code.to_string();
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output()?;
+        let written = writer.join().expect("Writing to rustfmt panicked");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            bail!("'{}' failed: {}", binary, stderr.trim())
+        }
+        written?;
+        let stdout = String::from_utf8(output.stdout)
+            .chain_err(|| format!("'{}' produced invalid UTF-8", binary))?;
+        Ok(stdout)
+    }
+}
+
+fn edition_flag(edition: Edition) -> &'static str {
+    match edition {
+        Edition::E2015 => "2015",
+        Edition::E2018 => "2018",
+        Edition::E2021 => "2021",
+        Edition::E2024 => "2024",
+    }
+}
+
+/// Where rendered code fails to parse, see `check_syntax`
+/// and `ItemMap::syntax_errors`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// The item whose output is malformed, if known
+    pub item: Option<Id>,
+    /// 1-based line within the rendered code. For `ItemMap::syntax_errors`
+    /// this counts from the start of the item, not of the rendered file.
+    pub line: usize,
+    /// 1-based column, in characters
+    pub column: usize,
+    pub message: String,
+    /// The offending line
+    pub snippet: String,
+}
+
+impl SyntaxError {
+    fn new(error: &syn::Error, code: &str) -> SyntaxError {
+        let start = error.span().start();
+        SyntaxError {
+            item: None,
+            line: start.line,
+            column: start.column + 1,
+            message: error.to_string(),
+            snippet: code.lines().nth(start.line.saturating_sub(1)).unwrap_or("").into(),
+        }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref item) = self.item {
+            write!(f, "in '{}' ", item)?;
+        }
+        writeln!(f, "at line {}, column {}: {}", self.line, self.column, self.message)?;
+        writeln!(f, "    {}", self.snippet)?;
+        write!(f, "    {}^", " ".repeat(self.column - 1))
+    }
+}
+
+/// Parse `code` as a Rust source file, locating the first error if it
+/// does not parse or if the contents of a `#[serde(...)]` attribute are
+/// malformed
+pub fn check_syntax(code: &str) -> ::std::result::Result<(), SyntaxError> {
+    let file = syn::parse_file(code).map_err(|e| SyntaxError::new(&e, code))?;
+    let mut check = SerdeCheck { error: None };
+    check.visit_file(&file);
+    match check.error {
+        Some(error) => Err(SyntaxError::new(&error, code)),
+        None => Ok(()),
+    }
+}
+
+/// serde options whose values must be string literals. Any other option
+/// only needs a Rust expression as its value.
+const SERDE_STRINGS: &[&str] = &[
+    "alias",
+    "borrow",
+    "bound",
+    "content",
+    "crate",
+    "default",
+    "deserialize_with",
+    "expecting",
+    "from",
+    "getter",
+    "into",
+    "remote",
+    "rename",
+    "rename_all",
+    "rename_all_fields",
+    "serialize_with",
+    "skip_serializing_if",
+    "tag",
+    "try_from",
+    "with",
+];
+
+/// Finds the first `#[serde(...)]` attribute which serde would reject
+/// as malformed
+struct SerdeCheck {
+    error: Option<syn::Error>,
+}
+
+impl<'ast> Visit<'ast> for SerdeCheck {
+    fn visit_attribute(&mut self, attr: &'ast syn::Attribute) {
+        if self.error.is_none() && attr.path().is_ident("serde") {
+            self.error = attr.parse_nested_meta(|meta| check_serde_meta(meta, false)).err();
+        }
+        visit::visit_attribute(self, attr);
+    }
+}
+
+/// Check one option such as `rename = "x"`, or each of those nested in
+/// `rename(serialize = "x")`. `string` is set within such a list.
+fn check_serde_meta(meta: ParseNestedMeta, string: bool) -> syn::Result<()> {
+    let string = string || SERDE_STRINGS.iter().any(|name| meta.path.is_ident(name));
+    if meta.input.peek(syn::token::Eq) {
+        let value = meta.value()?;
+        if string {
+            value.parse::<syn::LitStr>()?;
+        } else {
+            value.parse::<syn::Expr>()?;
+        }
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|inner| check_serde_meta(inner, string))?;
+    }
+    Ok(())
+}
+
+fn parse(code: &str) -> Result<syn::File> {
+    syn::parse_file(code)
+        .map_err(|e| ErrorKind::Syntax(SyntaxError::new(&e, code).to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+    use utils::has_rustfmt;
+
+    #[test]
+    fn test_format() {
+        let formatter = Formatter::default();
+        let pretty = formatter.format("pub struct Foo{x:u32,y:Vec<String>}").unwrap();
+        assert_eq!(pretty, "pub struct Foo {\n    x: u32,\n    y: Vec<String>,\n}\n");
+        // already formatted input is not an error
+        assert_eq!(formatter.format(&pretty).unwrap(), pretty);
+
+        match *formatter.format("pub struct Foo {").unwrap_err().kind() {
+            ErrorKind::Syntax(_) => (),
+            ref other => panic!("Expected a syntax error, got {}", other),
+        }
+
+        let missing = Backend::Rustfmt(Some("/no/such/rustfmt".into()));
+        let error = Formatter::default().backend(missing).format("struct Foo;").unwrap_err();
+        if let ErrorKind::Syntax(_) = *error.kind() {
+            panic!("A missing binary is not a syntax error")
+        }
+    }
+
+    #[test]
+    fn test_check_syntax() {
+        assert!(check_syntax("pub struct Foo {\n    x: u32,\n}").is_ok());
+        let error = check_syntax("pub struct Foo {\n    x: u32 y: u8,\n}").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 12));
+        assert_eq!(error.snippet, "    x: u32 y: u8,");
+        let shown = error.to_string();
+        assert!(shown.starts_with("at line 2, column 12: "));
+        assert!(shown.ends_with("\n        x: u32 y: u8,\n               ^"));
+
+        let serde = |attr: &str| check_syntax(&format!("#[serde({})]\nstruct Foo;", attr));
+        assert!(serde("rename = \"a\\\"b\", default, bound = \"T: Clone\"").is_ok());
+        assert!(serde("rename(serialize = \"a\", deserialize = \"b\")").is_ok());
+        let error = serde("tag = \"kind\", rename_all = bogus").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 36));
+        assert!(serde("rename = 1").is_err());
+        assert!(serde("rename(serialize = 1)").is_err());
+        assert!(serde("rename = ").is_err());
+        assert!(serde("default = \"Foo::new\", alias = \"b\", with = \"x\"").is_ok());
+        assert!(serde("default = Foo::new").is_err());
+        assert!(serde("skip_serializing_if = Option::is_none").is_err());
+        assert!(serde("bound(serialize = T)").is_err());
+    }
+
+    #[test]
+    fn test_rustfmt_backend() {
+        if !has_rustfmt() {
+            return;
+        }
+        let formatter = Formatter::default().backend(Backend::Rustfmt(None));
+        let pretty = formatter.format("pub struct Foo{x:u32}").unwrap();
+        assert_eq!(pretty, "pub struct Foo {\n    x: u32,\n}\n");
+        assert_eq!(formatter.format(&pretty).unwrap(), pretty);
+        match *formatter.format("pub struct Foo {").unwrap_err().kind() {
+            ErrorKind::Syntax(_) => (),
+            ref other => panic!("Expected a syntax error, got {}", other),
+        }
+        // more than fits in the pipes at once
+        let large: String = (0..10000).map(|ix| format!("struct S{}{{x:u32}}", ix)).collect();
+        assert_eq!(formatter.format(&large).unwrap().lines().count(), 10000 * 3);
+    }
+
+    #[test]
+    fn test_format_options() {
+        let code = "pub struct Foo{x:u32,y:Vec<String>}";
+        let tabs = FormatOptions::default().hard_tabs(true);
+        assert!(Formatter::default().options(tabs.clone()).format(code).is_err());
+        let narrow = Formatter::default().options(FormatOptions::default().max_width(40));
+        assert!(narrow.format(code).is_err());
+
+        if !has_rustfmt() {
+            return;
+        }
+        let options = FormatOptions::default()
+            .max_width(30)
+            .trailing_comma(TrailingComma::Never);
+        let formatter = Formatter::default().backend(Backend::Rustfmt(None)).options(options);
+        assert_eq!(
+            formatter.format(code).unwrap(),
+            "pub struct Foo {\n    x: u32,\n    y: Vec<String>\n}\n"
+        );
+        let expect = "pub struct Foo {\n\tx: u32,\n\ty: Vec<String>,\n}\n";
+        let formatter = Formatter::default().backend(Backend::Rustfmt(None)).options(tabs);
+        assert_eq!(formatter.format(code).unwrap(), expect);
+        // string literals keep their spaces
+        let literal = "const DOC: &str = \"first\n    second\";\n";
+        assert_eq!(formatter.format(literal).unwrap(), literal);
+
+        let dir = env::temp_dir().join(format!("simple-codegen-fmt-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("rustfmt.toml"), "hard_tabs = true\n").unwrap();
+        let from_file = Formatter::default()
+            .backend(Backend::Rustfmt(None))
+            .options(FormatOptions::default().config_path(dir.to_str().unwrap()));
+        let pretty = from_file.format(code);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(pretty.unwrap(), expect);
+    }
+}
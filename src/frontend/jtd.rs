@@ -0,0 +1,297 @@
+//! JSON Type Definition (RFC 8927) frontend
+//!
+//! Schema forms are mapped onto the crate model as follows:
+//!
+//! * `properties`/`optionalProperties` become a `Struct`, with optional
+//!   properties wrapped in `Type::Option`
+//! * `enum` becomes an `Enum` of unit variants
+//! * `discriminator` becomes an internally tagged `Enum`, with one
+//!   generated `Struct` per mapping entry
+//! * `elements` and `values` become `Vec<T>` and `Map<String, T>`
+//! * `ref` becomes a `Type::Named` reference to the definition
+//! * the empty form becomes `Value`, which must be in scope
+//!   (e.g. `use serde_json::Value`)
+//! * `timestamp` becomes a `String`, holding the RFC 3339 text as is
+//!
+//! Inline schemas that need their own item are named after their
+//! parent and the property they appear in (`Order` + `lines` = `OrderLines`).
+//! Definitions that are not structs or enums become an `Alias`.
+
+use std::collections::BTreeSet;
+
+use inflector::Inflector;
+use serde_json::{self, Map, Value};
+
+use errors::*;
+use {Alias, Attributes, Disambiguation, Enum, Field, Id, Item, ItemMap, Primitive, Serde, Struct,
+     Type, TypeId, Variant, Visibility};
+
+/// Parse a JTD schema. The root schema (if it is not the empty form)
+/// becomes an item called `root_name`, and every entry of `definitions`
+/// an item named after its key.
+pub fn parse(json: &str, root_name: &str) -> Result<ItemMap> {
+    let schema: Value = serde_json::from_str(json)?;
+    let schema = as_object(&schema, root_name)?;
+    let mut importer = Importer::default();
+    if let Some(defs) = schema.get("definitions") {
+        let defs = as_object(defs, "definitions")?;
+        for (name, def) in defs {
+            if as_object(def, name)?.get("nullable") == Some(&Value::Bool(true)) {
+                importer.nullable.insert(name.clone());
+            }
+        }
+        for (name, def) in defs {
+            importer.definition(def, name)?;
+        }
+    }
+    if !is_empty_form(schema) {
+        importer.definition(&Value::Object(schema.clone()), root_name)?;
+    }
+    ItemMap::build(importer.items)
+}
+
+#[derive(Default)]
+struct Importer {
+    items: Vec<Box<Item>>,
+    /// Definitions which are nullable, so references to them are optional
+    nullable: BTreeSet<String>,
+}
+
+impl Importer {
+    /// Emit an item for a top-level schema, aliasing it if
+    /// it does not produce one of its own
+    fn definition(&mut self, schema: &Value, name: &str) -> Result<()> {
+        let id = type_id(name)?;
+        let typ = self.form_type(as_object(schema, name)?, name)?;
+        if typ != Type::Named(id.clone().into()) {
+            self.items.push(Box::new(Alias::new(id, Visibility::Public, typ)));
+        }
+        Ok(())
+    }
+
+    /// The type of a schema, honouring `nullable`
+    fn schema_type(&mut self, schema: &Value, name: &str) -> Result<Type> {
+        let schema = as_object(schema, name)?;
+        let nullable = schema.get("nullable") == Some(&Value::Bool(true));
+        Ok(self.form_type(schema, name)?.optional(nullable))
+    }
+
+    /// The type of a schema ignoring `nullable`. Forms which need
+    /// a named item create it and return a reference to it.
+    fn form_type(&mut self, schema: &Map<String, Value>, name: &str) -> Result<Type> {
+        if let Some(typ) = schema.get("type") {
+            return primitive(as_str(typ, name)?);
+        }
+        if let Some(reference) = schema.get("ref") {
+            let reference = as_str(reference, name)?;
+            let nullable = self.nullable.contains(reference);
+            return Ok(Type::Named(type_id(reference)?.into()).optional(nullable));
+        }
+        if let Some(elements) = schema.get("elements") {
+            let inner = self.schema_type(elements, name)?;
+            return Ok(Type::Vec(Box::new(inner)));
+        }
+        if let Some(values) = schema.get("values") {
+            let inner = self.schema_type(values, name)?;
+            return Ok(Type::Map(
+                Box::new(Type::Primitive(Primitive::String)),
+                Box::new(inner),
+            ));
+        }
+        let id = type_id(name)?;
+        if let Some(values) = schema.get("enum") {
+            let variants = match *values {
+                Value::Array(ref values) => {
+                    values
+                        .iter()
+                        .map(|v| Variant::with_rename(as_str(v, name)?, None))
+                        .collect::<Result<Vec<_>>>()?
+                }
+                _ => bail!("'enum' of '{}' is not an array", name),
+            };
+            self.push_enum(id.clone(), Attributes::default(), variants)?;
+        } else if let Some(tag) = schema.get("discriminator") {
+            let tag = as_str(tag, name)?;
+            let mapping = match schema.get("mapping") {
+                Some(mapping) => as_object(mapping, name)?,
+                None => bail!("discriminator '{}' has no mapping", name),
+            };
+            let mut variants = Vec::new();
+            for (value, schema) in mapping {
+                let struct_name = format!("{}{}", id, value.to_pascal_case());
+                let typ = self.form_type(as_object(schema, value)?, &struct_name)?;
+                variants.push(Variant::with_rename(value, Some(typ))?);
+            }
+            let attrs = Attributes::default().serde(&[Serde::Tag(tag.into())]);
+            self.push_enum(id.clone(), attrs, variants)?;
+        } else if schema.contains_key("properties") || schema.contains_key("optionalProperties") {
+            let mut fields = Vec::new();
+            for &(key, optional) in &[("properties", false), ("optionalProperties", true)] {
+                if let Some(props) = schema.get(key) {
+                    for (prop, schema) in as_object(props, name)? {
+                        let child = format!("{}{}", id, prop.to_pascal_case());
+                        // nullable and optional collapse into one `Option`
+                        let typ = match self.schema_type(schema, &child)? {
+                            typ @ Type::Option(_) => typ,
+                            typ => typ.optional(optional),
+                        };
+                        fields.push(Field::with_rename(prop.clone(), typ)?);
+                    }
+                }
+            }
+            self.items.push(Box::new(Struct::disambiguated(
+                id.clone(),
+                Visibility::Public,
+                Attributes::default(),
+                fields,
+                &Disambiguation::default(),
+            )?));
+        } else {
+            return Type::named("Value");
+        }
+        Ok(Type::Named(id.into()))
+    }
+
+    fn push_enum(&mut self, id: TypeId, attrs: Attributes, variants: Vec<Variant>) -> Result<()> {
+        let strategy = Disambiguation::default();
+        self.items.push(Box::new(
+            Enum::disambiguated(id, Visibility::Public, attrs, variants, &strategy)?,
+        ));
+        Ok(())
+    }
+}
+
+fn primitive(name: &str) -> Result<Type> {
+    use Primitive::*;
+    let prim = match name {
+        "boolean" => Boolean,
+        "string" | "timestamp" => String,
+        "float32" => F32,
+        "float64" => F64,
+        "int8" => I8,
+        "uint8" => U8,
+        "int16" => I16,
+        "uint16" => U16,
+        "int32" => I32,
+        "uint32" => U32,
+        other => bail!("Unknown JTD type '{}'", other),
+    };
+    Ok(Type::Primitive(prim))
+}
+
+fn is_empty_form(schema: &Map<String, Value>) -> bool {
+    schema.keys().all(|k| {
+        k == "definitions" || k == "metadata" || k == "nullable"
+    })
+}
+
+fn type_id(name: &str) -> Result<TypeId> {
+    TypeId::from_id(Id::make_valid(name.to_pascal_case())?)
+}
+
+fn as_object<'a>(value: &'a Value, context: &str) -> Result<&'a Map<String, Value>> {
+    match *value {
+        Value::Object(ref map) => Ok(map),
+        _ => bail!("Schema for '{}' is not an object", context),
+    }
+}
+
+fn as_str<'a>(value: &'a Value, context: &str) -> Result<&'a str> {
+    match *value {
+        Value::String(ref s) => Ok(s),
+        _ => bail!("Expected a string in schema for '{}'", context),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::rendered;
+
+    #[test]
+    fn test_parse_jtd() {
+        let src = r#"{
+            "definitions": {
+                "status": { "enum": ["in-progress", "Done"] },
+                "note": { "type": "string", "nullable": true }
+            },
+            "properties": {
+                "id": { "type": "uint32" },
+                "status": { "ref": "status" },
+                "lines": {
+                    "elements": {
+                        "properties": { "sku": { "type": "string" } }
+                    }
+                },
+                "event": {
+                    "discriminator": "eventType",
+                    "mapping": {
+                        "created": {
+                            "properties": { "at": { "type": "timestamp" } }
+                        },
+                        "cancelled": {
+                            "optionalProperties": { "reason": { "ref": "note" } }
+                        }
+                    }
+                }
+            },
+            "optionalProperties": {
+                "tags": { "values": { "type": "boolean" } },
+                "extra": {}
+            }
+        }"#;
+        let map = parse(src, "order").unwrap();
+        let render = |name: &str| {
+            rendered(map.get(&Id::new(name).unwrap()).unwrap())
+        };
+        assert_eq!(
+            render("Order"),
+            r#"pub struct Order {
+    event: OrderEvent,
+    id: u32,
+    lines: Vec<OrderLines>,
+    status: Status,
+    extra: Option<Value>,
+    tags: Option<Map<String, bool>>,
+}
+"#
+        );
+        assert_eq!(
+            render("Status"),
+            r#"pub enum Status {
+    #[serde(rename = "in-progress")]
+    InProgress,
+    Done,
+}
+"#
+        );
+        assert_eq!(render("Note"), "pub type Note = String;\n");
+        assert_eq!(
+            render("OrderLines"),
+            "pub struct OrderLines {\n    sku: String,\n}\n"
+        );
+        assert_eq!(
+            render("OrderEvent"),
+            r#"#[serde(tag = "eventType")]
+pub enum OrderEvent {
+    #[serde(rename = "cancelled")]
+    Cancelled(OrderEventCancelled),
+    #[serde(rename = "created")]
+    Created(OrderEventCreated),
+}
+"#
+        );
+        assert_eq!(
+            render("OrderEventCancelled"),
+            "pub struct OrderEventCancelled {\n    reason: Option<Note>,\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_jtd_errors() {
+        assert!(parse("[]", "Root").is_err());
+        assert!(parse(r#"{"type": "int64"}"#, "Root").is_err());
+        assert!(parse(r#"{"discriminator": "t"}"#, "Root").is_err());
+        assert!(parse(r#"{"enum": "a"}"#, "Root").is_err());
+    }
+}
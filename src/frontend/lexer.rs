@@ -0,0 +1,252 @@
+//! A small tokenizer shared by the C-like IDL frontends
+
+use errors::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Ident(String),
+    Str(String),
+    /// Numeric literal, kept as written
+    Number(String),
+    Punct(char),
+}
+
+/// Token stream with one token of lookahead. Every token remembers
+/// the line it came from so errors can point back into the source.
+pub(crate) struct Tokens {
+    toks: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+/// Split `src` into tokens, discarding whitespace and comments.
+/// `//` and `/* */` comments are always recognised, `#` comments
+/// only when `hash_comments` is set.
+pub(crate) fn tokenize(src: &str, hash_comments: bool) -> Result<Tokens> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut toks = Vec::new();
+    let mut line = 1;
+    let mut ix = 0;
+    while ix < chars.len() {
+        let c = chars[ix];
+        let next = chars.get(ix + 1).cloned();
+        if c == '\n' {
+            line += 1;
+            ix += 1;
+        } else if c.is_whitespace() {
+            ix += 1;
+        } else if (c == '/' && next == Some('/')) || (c == '#' && hash_comments) {
+            while ix < chars.len() && chars[ix] != '\n' {
+                ix += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            ix += 2;
+            loop {
+                match chars.get(ix) {
+                    Some(&'*') if chars.get(ix + 1) == Some(&'/') => break,
+                    Some(&'\n') => line += 1,
+                    Some(_) => (),
+                    None => bail!("line {}: unterminated comment", line),
+                }
+                ix += 1;
+            }
+            ix += 2;
+        } else if c == '"' || c == '\'' {
+            let start = line;
+            let mut s = String::new();
+            ix += 1;
+            loop {
+                match chars.get(ix) {
+                    Some(&q) if q == c => break,
+                    Some(&'\\') => {
+                        ix += 1;
+                        match chars.get(ix) {
+                            Some(&'n') => s.push('\n'),
+                            Some(&'t') => s.push('\t'),
+                            Some(&'r') => s.push('\r'),
+                            Some(&other) => s.push(other),
+                            None => bail!("line {}: unterminated string", start),
+                        }
+                    }
+                    Some(&other) => {
+                        if other == '\n' {
+                            line += 1;
+                        }
+                        s.push(other)
+                    }
+                    None => bail!("line {}: unterminated string", start),
+                }
+                ix += 1;
+            }
+            ix += 1;
+            toks.push((Token::Str(s), start));
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let mut s = String::new();
+            while ix < chars.len() &&
+                (chars[ix].is_alphanumeric() || chars[ix] == '_' || chars[ix] == '$' ||
+                     chars[ix] == '.')
+            {
+                s.push(chars[ix]);
+                ix += 1;
+            }
+            toks.push((Token::Ident(s), line));
+        } else if c.is_ascii_digit() ||
+                   ((c == '-' || c == '+') && next.map(|n| n.is_ascii_digit()).unwrap_or(false))
+        {
+            let mut s = String::new();
+            s.push(c);
+            ix += 1;
+            while ix < chars.len() &&
+                (chars[ix].is_alphanumeric() || chars[ix] == '.' ||
+                     ((chars[ix] == '-' || chars[ix] == '+') &&
+                          (chars[ix - 1] == 'e' || chars[ix - 1] == 'E')))
+            {
+                s.push(chars[ix]);
+                ix += 1;
+            }
+            toks.push((Token::Number(s), line));
+        } else {
+            toks.push((Token::Punct(c), line));
+            ix += 1;
+        }
+    }
+    Ok(Tokens { toks, pos: 0 })
+}
+
+impl Tokens {
+    pub(crate) fn peek(&self) -> Option<&Token> {
+        self.toks.get(self.pos).map(|(t, _)| t)
+    }
+
+    pub(crate) fn line(&self) -> usize {
+        self.toks
+            .get(self.pos)
+            .or_else(|| self.toks.last())
+            .map(|&(_, line)| line)
+            .unwrap_or(1)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.toks.len()
+    }
+
+    pub(crate) fn next(&mut self) -> Result<Token> {
+        match self.toks.get(self.pos) {
+            Some((tok, _)) => {
+                self.pos += 1;
+                Ok(tok.clone())
+            }
+            None => bail!("line {}: unexpected end of input", self.line()),
+        }
+    }
+
+    pub(crate) fn peek_punct(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    pub(crate) fn peek_ident(&self, ident: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(s)) => s == ident,
+            _ => false,
+        }
+    }
+
+    /// Consume the next token if it is the given punctuation
+    pub(crate) fn eat_punct(&mut self, c: char) -> bool {
+        if self.peek_punct(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the next token if it is the given identifier
+    pub(crate) fn eat_ident(&mut self, ident: &str) -> bool {
+        if self.peek_ident(ident) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn expect_punct(&mut self, c: char) -> Result<()> {
+        let line = self.line();
+        match self.next()? {
+            Token::Punct(p) if p == c => Ok(()),
+            other => bail!("line {}: expected '{}', found {:?}", line, c, other),
+        }
+    }
+
+    pub(crate) fn expect_ident(&mut self) -> Result<String> {
+        let line = self.line();
+        match self.next()? {
+            Token::Ident(s) => Ok(s),
+            other => bail!("line {}: expected identifier, found {:?}", line, other),
+        }
+    }
+
+    pub(crate) fn expect_str(&mut self) -> Result<String> {
+        let line = self.line();
+        match self.next()? {
+            Token::Str(s) => Ok(s),
+            other => bail!("line {}: expected string literal, found {:?}", line, other),
+        }
+    }
+
+    /// Skip a balanced group, assuming the opening delimiter
+    /// has already been consumed
+    pub(crate) fn skip_group(&mut self, open: char, close: char) -> Result<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct(c) if c == open => depth += 1,
+                Token::Punct(c) if c == close => depth -= 1,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let src = r#"
+            # a comment
+            struct Foo { // another
+                1: optional list<i32> bar = -12, /* and
+                a block */
+                2: string baz = "q\"uote"
+            }
+        "#;
+        let mut toks = tokenize(src, true).unwrap();
+        assert_eq!(toks.next().unwrap(), Token::Ident("struct".into()));
+        assert_eq!(toks.next().unwrap(), Token::Ident("Foo".into()));
+        assert!(toks.eat_punct('{'));
+        assert_eq!(toks.next().unwrap(), Token::Number("1".into()));
+        toks.expect_punct(':').unwrap();
+        assert!(toks.eat_ident("optional"));
+        assert_eq!(toks.expect_ident().unwrap(), "list");
+        toks.expect_punct('<').unwrap();
+        toks.expect_ident().unwrap();
+        toks.expect_punct('>').unwrap();
+        toks.expect_ident().unwrap();
+        toks.expect_punct('=').unwrap();
+        assert_eq!(toks.next().unwrap(), Token::Number("-12".into()));
+        toks.expect_punct(',').unwrap();
+        assert_eq!(toks.line(), 6);
+        toks.next().unwrap();
+        toks.next().unwrap();
+        toks.next().unwrap();
+        toks.next().unwrap();
+        toks.next().unwrap();
+        assert_eq!(toks.expect_str().unwrap(), "q\"uote");
+        toks.expect_punct('}').unwrap();
+        assert!(toks.is_empty());
+        assert!(toks.next().is_err());
+    }
+}
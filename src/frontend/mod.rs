@@ -0,0 +1,7 @@
+//! Importers which build an `ItemMap` from other schema languages
+
+mod lexer;
+pub mod jtd;
+pub mod rust;
+pub mod thrift;
+pub mod typescript;
@@ -0,0 +1,546 @@
+//! Rust source frontend
+//!
+//! Parses structs, enums, newtypes, type aliases and consts back into the
+//! crate model, so hand-written types can be transformed and re-emitted.
+//! Other items (`use`, `impl`, `fn`, ...) and doc comments are skipped.
+//!
+//! Attributes are mapped to their typed equivalents where one exists
+//! (`Derive`, `Cfg`, `Serde`, `FieldAttr::SerdeRename`, ...) and kept
+//! verbatim as custom attributes otherwise.
+//!
+//! Input which would be re-emitted as different or invalid Rust is
+//! rejected: unit structs, newtypes whose field is `pub`, discriminants
+//! other than integer literals, references without a lifetime outside of
+//! consts, and maps with keys other than `String`, since every map is
+//! emitted as a `serde_json::Map`.
+
+use prettyplease;
+use quote::ToTokens;
+use syn;
+use syn::ext::IdentExt;
+use syn::punctuated::Punctuated;
+
+use errors::*;
+use {Alias, Attributes, Case, Cfg, Const, Derive, Enum, Field, FieldAttr, Id, Item, ItemMap,
+     Lifetime, NewType, Path, Serde, Struct, Type, TypeId, ValueId, Variant, Visibility};
+
+/// Parse Rust source into an `ItemMap`
+pub fn parse(source: &str) -> Result<ItemMap> {
+    let file = syn::parse_file(source).map_err(
+        |e| format!("Failed to parse Rust source: {}", e),
+    )?;
+    let mut items: Vec<Box<Item>> = Vec::new();
+    for item in &file.items {
+        match *item {
+            syn::Item::Struct(ref s) => items.push(parse_struct(s)?),
+            syn::Item::Enum(ref e) => items.push(Box::new(parse_enum(e)?)),
+            syn::Item::Type(ref t) => {
+                if !t.generics.params.is_empty() {
+                    bail!("Generic type alias '{}' is not supported", t.ident)
+                }
+                items.push(Box::new(Alias::new(
+                    TypeId::from_id(ident(&t.ident)?)?,
+                    visibility(&t.vis)?,
+                    stored_typ(&t.ty)?,
+                )))
+            }
+            syn::Item::Const(ref c) => {
+                items.push(Box::new(Const::new(
+                    ValueId::constant_from_id(ident(&c.ident)?)?,
+                    visibility(&c.vis)?,
+                    typ(&c.ty)?,
+                    c.expr.to_token_stream().to_string(),
+                )))
+            }
+            _ => (),
+        }
+    }
+    ItemMap::build(items)
+}
+
+fn parse_struct(s: &syn::ItemStruct) -> Result<Box<Item>> {
+    if !s.generics.params.is_empty() {
+        bail!("Generic struct '{}' is not supported", s.ident)
+    }
+    let name = TypeId::from_id(ident(&s.ident)?)?;
+    let vis = visibility(&s.vis)?;
+    let attrs = attributes(&s.attrs)?;
+    match s.fields {
+        syn::Fields::Named(ref named) => {
+            let fields = named
+                .named
+                .iter()
+                .map(|f| {
+                    Ok(Field::new(
+                        ValueId::from_id(ident(f.ident.as_ref().unwrap())?)?,
+                        stored_typ(&f.ty)?,
+                        field_attrs(&f.attrs)?,
+                    ).vis(visibility(&f.vis)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Box::new(Struct::new(name, vis, attrs, fields)?))
+        }
+        syn::Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => {
+            let field = &unnamed.unnamed[0];
+            match field.vis {
+                syn::Visibility::Inherited => (),
+                _ => bail!("Newtype '{}' with a visible field is not supported", s.ident),
+            }
+            let inner = stored_typ(&field.ty)?;
+            Ok(Box::new(NewType::new(name, vis, attrs, inner)))
+        }
+        syn::Fields::Unit => bail!("Unit struct '{}' is not supported", s.ident),
+        _ => bail!("Tuple struct '{}' must have exactly one field", s.ident),
+    }
+}
+
+fn parse_enum(e: &syn::ItemEnum) -> Result<Enum> {
+    if !e.generics.params.is_empty() {
+        bail!("Generic enum '{}' is not supported", e.ident)
+    }
+    let variants = e.variants
+        .iter()
+        .map(|v| {
+            let inner = match v.fields {
+                syn::Fields::Unit => None,
+                syn::Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => {
+                    Some(stored_typ(&unnamed.unnamed[0].ty)?)
+                }
+                _ => {
+                    bail!(
+                        "Variant '{}::{}' must be a unit or single-field tuple variant",
+                        e.ident,
+                        v.ident
+                    )
+                }
+            };
+            let name = TypeId::from_id(ident(&v.ident)?)?;
+            let variant = Variant::new(name, inner.clone(), field_attrs(&v.attrs)?);
+            match v.discriminant {
+                Some((_, ref expr)) if inner.is_none() => {
+                    Ok(variant.discriminant(discriminant(expr).chain_err(|| {
+                        format!("Invalid discriminant of '{}::{}'", e.ident, v.ident)
+                    })?))
+                }
+                Some(_) => {
+                    bail!("Variant '{}::{}' has fields and a discriminant", e.ident, v.ident)
+                }
+                None => Ok(variant),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Enum::new(
+        TypeId::from_id(ident(&e.ident)?)?,
+        visibility(&e.vis)?,
+        attributes(&e.attrs)?,
+        variants,
+    )
+}
+
+/// An integer literal such as `5` or `-1`
+fn discriminant(expr: &syn::Expr) -> Result<i64> {
+    match *expr {
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Int(ref int), .. }) => {
+            int.base10_parse().map_err(|e| e.to_string().into())
+        }
+        syn::Expr::Unary(syn::ExprUnary { op: syn::UnOp::Neg(_), ref expr, .. }) => {
+            discriminant(expr).map(|value| -value)
+        }
+        _ => bail!("Unsupported discriminant '{}'", expr.to_token_stream()),
+    }
+}
+
+/// Raw identifiers are only kept raw where they need to be (`r#type`)
+fn ident(ident: &syn::Ident) -> Result<Id> {
+    Id::new(ident.unraw().to_string()).or_else(|_| Id::new(ident.to_string()))
+}
+
+fn visibility(vis: &syn::Visibility) -> Result<Visibility> {
+    match *vis {
+        syn::Visibility::Inherited => Ok(Visibility::Private),
+        syn::Visibility::Public(_) => Ok(Visibility::Public),
+        syn::Visibility::Restricted(ref r) if r.path.is_ident("crate") && r.in_token.is_none() => {
+            Ok(Visibility::Crate)
+        }
+        ref other => bail!("Unsupported visibility '{}'", other.to_token_stream()),
+    }
+}
+
+/// The type of a field, variant, newtype or alias, where a reference
+/// must have a lifetime
+fn stored_typ(ty: &syn::Type) -> Result<Type> {
+    let parsed = typ(ty)?;
+    if has_elided_lifetime(&parsed) {
+        bail!("Reference without a lifetime in '{}'", ty.to_token_stream())
+    }
+    Ok(parsed)
+}
+
+fn has_elided_lifetime(typ: &Type) -> bool {
+    match *typ {
+        Type::Ref(None, _) => true,
+        Type::Ref(Some(_), ref inner) |
+        Type::Box(ref inner) |
+        Type::Vec(ref inner) |
+        Type::Option(ref inner) => has_elided_lifetime(inner),
+        Type::Result(ref t1, ref t2) | Type::Map(ref t1, ref t2) => {
+            has_elided_lifetime(t1) || has_elided_lifetime(t2)
+        }
+        Type::Tuple(ref types) => types.iter().any(has_elided_lifetime),
+        Type::Path(ref path) => path.args().iter().any(has_elided_lifetime),
+        Type::Primitive(_) | Type::Named(_) => false,
+    }
+}
+
+fn typ(ty: &syn::Type) -> Result<Type> {
+    use Primitive::*;
+    match *ty {
+        syn::Type::Tuple(ref t) if t.elems.is_empty() => Ok(Type::Primitive(Null)),
+        syn::Type::Tuple(ref t) => Ok(Type::Tuple(t.elems.iter().map(typ).collect::<Result<_>>()?)),
+        syn::Type::Reference(ref r) => {
+            let lifetime = match r.lifetime {
+                Some(ref lifetime) => Some(Lifetime::new(lifetime.to_string())?),
+                None => None,
+            };
+            Ok(Type::Ref(lifetime, Box::new(typ(&r.elem)?)))
+        }
+        syn::Type::Paren(ref p) => typ(&p.elem),
+        syn::Type::Path(ref p) if p.qself.is_none() => {
+            let segment = p.path.segments.last().unwrap();
+            if p.path.segments.iter().rev().skip(1).any(|s| !s.arguments.is_empty()) {
+                bail!("Generic arguments must be on the last segment of '{}'", ty.to_token_stream())
+            }
+            let args: Vec<Type> = match segment.arguments {
+                syn::PathArguments::None => vec![],
+                syn::PathArguments::AngleBracketed(ref args) => {
+                    args.args
+                        .iter()
+                        .map(|arg| match *arg {
+                            syn::GenericArgument::Type(ref t) => typ(t),
+                            _ => {
+                                bail!("Unsupported generic argument in '{}'", ty.to_token_stream())
+                            }
+                        })
+                        .collect::<Result<_>>()?
+                }
+                syn::PathArguments::Parenthesized(_) => {
+                    bail!("Unsupported type '{}'", ty.to_token_stream())
+                }
+            };
+            let name = segment.ident.to_string();
+            let mut args = args.into_iter();
+            let typ = match (&*name, args.len()) {
+                ("Box", 1) => Type::Box(Box::new(args.next().unwrap())),
+                ("Vec", 1) => Type::Vec(Box::new(args.next().unwrap())),
+                ("Option", 1) => Type::Option(Box::new(args.next().unwrap())),
+                ("Result", 2) => {
+                    Type::Result(Box::new(args.next().unwrap()), Box::new(args.next().unwrap()))
+                }
+                ("Map", 2) | ("HashMap", 2) | ("BTreeMap", 2) => {
+                    let key = args.next().unwrap();
+                    if key != Type::Primitive(String) {
+                        bail!("Map '{}' must have String keys", ty.to_token_stream())
+                    }
+                    Type::Map(Box::new(key), Box::new(args.next().unwrap()))
+                }
+                (_, 0) if p.path.segments.len() == 1 => {
+                    match &*name {
+                        "bool" => Type::Primitive(Boolean),
+                        "i8" => Type::Primitive(I8),
+                        "i16" => Type::Primitive(I16),
+                        "i32" => Type::Primitive(I32),
+                        "i64" => Type::Primitive(I64),
+                        "u8" => Type::Primitive(U8),
+                        "u16" => Type::Primitive(U16),
+                        "u32" => Type::Primitive(U32),
+                        "f32" => Type::Primitive(F32),
+                        "f64" => Type::Primitive(F64),
+                        "String" => Type::Primitive(String),
+                        "str" => Type::Primitive(Str),
+                        _ => Type::Named(ident(&segment.ident)?),
+                    }
+                }
+                _ => {
+                    let segments: Vec<_> =
+                        p.path.segments.iter().map(|s| s.ident.to_string()).collect();
+                    let global = if p.path.leading_colon.is_some() { "::" } else { "" };
+                    let path = Path::new(&format!("{}{}", global, segments.join("::")))?;
+                    Type::Path(path.with_args(args.collect()))
+                }
+            };
+            Ok(typ)
+        }
+        _ => bail!("Unsupported type '{}'", ty.to_token_stream()),
+    }
+}
+
+/// The comma-separated contents of a list attribute such as `#[serde(...)]`
+fn nested(meta: &syn::Meta) -> Result<Vec<syn::Meta>> {
+    let list = meta.require_list().map_err(|e| e.to_string())?;
+    let nested = list.parse_args_with(Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+        .map_err(|e| format!("Failed to parse attribute '{}': {}", meta.to_token_stream(), e))?;
+    Ok(nested.into_iter().collect())
+}
+
+/// `meta` as written inside `#[...]`, spaced as a formatter would space
+/// it rather than with a space between every token
+fn meta_text(meta: &syn::Meta) -> String {
+    let file: syn::File = syn::parse_quote!(#[#meta] struct S;);
+    let text = prettyplease::unparse(&file);
+    let attr = text[..text.rfind("struct S;").unwrap()].trim();
+    attr["#[".len()..attr.len() - "]".len()].to_string()
+}
+
+fn string_value(nv: &syn::MetaNameValue) -> Result<String> {
+    match nv.value {
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(ref s), .. }) => Ok(s.value()),
+        _ => bail!("Expected a string in '{}'", nv.to_token_stream()),
+    }
+}
+
+fn attributes(attrs: &[syn::Attribute]) -> Result<Attributes> {
+    let mut derives = Vec::new();
+    let mut cfgs = Vec::new();
+    let mut serdes = Vec::new();
+    let mut customs = Vec::new();
+    for attr in attrs {
+        let meta = &attr.meta;
+        if meta.path().is_ident("doc") {
+            continue;
+        } else if meta.path().is_ident("derive") {
+            for d in nested(meta)? {
+                let derive = meta_text(&d);
+                derives.push(match &*derive {
+                    "Debug" => Derive::Debug,
+                    "Copy" => Derive::Copy,
+                    "Clone" => Derive::Clone,
+                    "PartialEq" => Derive::PartialEq,
+                    "Eq" => Derive::Eq,
+                    "Hash" => Derive::Hash,
+                    "PartialOrd" => Derive::PartialOrd,
+                    "Ord" => Derive::Ord,
+                    "Default" => Derive::Default,
+                    _ => Derive::Custom(derive),
+                });
+            }
+        } else if meta.path().is_ident("cfg") {
+            for c in nested(meta)? {
+                cfgs.push(match c {
+                    syn::Meta::Path(ref p) if p.is_ident("test") => Cfg::Test,
+                    syn::Meta::NameValue(ref nv) if nv.path.is_ident("target_os") => {
+                        Cfg::TargetOs(string_value(nv)?)
+                    }
+                    other => Cfg::Custom(meta_text(&other)),
+                });
+            }
+        } else if meta.path().is_ident("serde") {
+            for s in nested(meta)? {
+                serdes.push(match s {
+                    syn::Meta::Path(ref p) if p.is_ident("untagged") => Serde::Untagged,
+                    syn::Meta::NameValue(ref nv) if nv.path.is_ident("tag") => {
+                        Serde::Tag(string_value(nv)?)
+                    }
+                    syn::Meta::NameValue(ref nv) if nv.path.is_ident("content") => {
+                        Serde::Content(string_value(nv)?)
+                    }
+                    syn::Meta::NameValue(ref nv) if nv.path.is_ident("rename_all") => {
+                        let rule = string_value(nv)?;
+                        match Case::from_serde_name(&rule) {
+                            Some(case) => Serde::RenameAll(case),
+                            None => bail!("Unknown rename_all rule '{}'", rule),
+                        }
+                    }
+                    other => Serde::Custom(meta_text(&other)),
+                });
+            }
+        } else {
+            customs.push(meta_text(meta));
+        }
+    }
+    Ok(
+        Attributes::default()
+            .derive(&derives)
+            .cfg(&cfgs)
+            .serde(&serdes)
+            .custom(&customs),
+    )
+}
+
+fn field_attrs(attrs: &[syn::Attribute]) -> Result<Vec<FieldAttr>> {
+    let mut out = Vec::new();
+    for attr in attrs {
+        let meta = &attr.meta;
+        if meta.path().is_ident("doc") {
+            continue;
+        } else if meta.path().is_ident("serde") {
+            for s in nested(meta)? {
+                out.push(match s {
+                    syn::Meta::Path(ref p) if p.is_ident("default") => FieldAttr::SerdeDefault,
+                    syn::Meta::NameValue(ref nv) if nv.path.is_ident("rename") => {
+                        FieldAttr::SerdeRename(string_value(nv)?)
+                    }
+                    other => FieldAttr::Custom(format!("#[serde({})]", meta_text(&other))),
+                });
+            }
+        } else {
+            out.push(FieldAttr::Custom(format!("#[{}]", meta_text(meta))));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::rendered;
+    use Primitive;
+    use Derive::*;
+    use Cfg::*;
+    use FieldAttr::*;
+
+    #[test]
+    fn test_parse_rust() {
+        let src = r#"
+            use std::collections::BTreeMap;
+
+            /// Documented
+            #[derive(Debug, Clone, Serialize)]
+            #[cfg(test, target_os = "linux")]
+            #[serde(tag = "kind", rename_all = "camelCase")]
+            #[repr(C)]
+            pub(crate) struct MyStruct {
+                #[serde(rename = "Field-1", default)]
+                field1: Option<Box<Type1>>,
+                pub r#field2: BTreeMap<String, Vec<&'static str>>,
+                #[allow(dead_code)]
+                field3: Result<(), f64>,
+            }
+
+            pub enum MyEnum {
+                #[serde(rename = "variant-1")]
+                Variant1,
+                Variant2(MyStruct),
+            }
+
+            enum Level {
+                Low = -1,
+                High = 5,
+            }
+
+            struct MyNewType(u32);
+            pub type MyAlias = Vec<MyNewType>;
+            const MAX: i64 = 1 << 10;
+
+            impl MyStruct {
+                fn ignored(&self) {}
+            }
+        "#;
+        let map = parse(src).unwrap();
+        let render = |name: &str| {
+            rendered(map.get(&Id::new(name).unwrap()).unwrap())
+        };
+        assert_eq!(
+            render("MyStruct"),
+            r#"#[derive(Debug, Clone, Serialize)]
+#[cfg(test, target_os = "linux")]
+#[serde(tag = "kind", rename_all = "camelCase")]
+#[repr(C)]
+pub(crate) struct MyStruct {
+    #[serde(rename = "Field-1")]
+    #[serde(default)]
+    field1: Option<Box<Type1>>,
+    pub field2: Map<String, Vec<&'static str>>,
+    #[allow(dead_code)]
+    field3: Result<(), f64>,
+}
+"#
+        );
+        assert_eq!(
+            render("MyEnum"),
+            r#"pub enum MyEnum {
+    #[serde(rename = "variant-1")]
+    Variant1,
+    Variant2(MyStruct),
+}
+"#
+        );
+        assert_eq!(render("Level"), "enum Level {\n    Low = -1,\n    High = 5,\n}\n");
+        assert_eq!(render("MyNewType"), "struct MyNewType(u32);\n");
+        assert_eq!(render("MyAlias"), "pub type MyAlias = Vec<MyNewType>;\n");
+        assert_eq!(render("MAX"), "const MAX: i64 = 1 << 10;\n");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let s = Struct::new(
+            TypeId::new("MyStruct").unwrap(),
+            Visibility::Public,
+            Attributes::default()
+                .derive(&[Clone, Debug, Derive::Custom("Deserialize".into())])
+                .cfg(&[Test, TargetOs("linux".into())])
+                .serde(&[Serde::Untagged]),
+            vec![
+                Field::new(
+                    ValueId::new("field1").unwrap(),
+                    Type::Vec(Box::new(Type::named("Type1").unwrap())),
+                    vec![SerdeRename("Field-1".into()), SerdeDefault]
+                ),
+                Field::with_rename("Snake Case Me", Type::Primitive(Primitive::I32)).unwrap(),
+            ],
+        ).unwrap();
+        let map = parse(&s.to_string()).unwrap();
+        let parsed = map.get(&Id::new("MyStruct").unwrap()).unwrap();
+        assert_eq!(parsed.to_string(), s.to_string());
+    }
+
+    #[test]
+    fn test_parse_paths() {
+        let map = parse(
+            "struct Event {
+                payload: serde_json::Value,
+                at: ::std::time::SystemTime,
+                seen: chrono::DateTime<Utc>,
+                tags: Set<Tag>,
+            }",
+        ).unwrap();
+        let expect = "struct Event {
+    payload: serde_json::Value,
+    at: ::std::time::SystemTime,
+    seen: chrono::DateTime<Utc>,
+    tags: Set<Tag>,
+}";
+        let event = map.get(&Id::new("Event").unwrap()).unwrap();
+        assert_eq!(event.to_string(), expect);
+        let datetime = Path::new("chrono::DateTime").unwrap().with_args(vec![
+            Type::named("Utc").unwrap(),
+        ]);
+        assert_eq!(event.kind().members()[2].1, &Type::Path(datetime));
+        let unresolved: Vec<String> = map.unresolved().iter().map(|u| u.to_string()).collect();
+        assert_eq!(unresolved, vec!["Event.seen -> Utc", "Event.tags -> Tag"]);
+    }
+
+    #[test]
+    fn test_parse_rust_errors() {
+        assert!(parse("struct Foo {").is_err());
+        assert!(parse("struct Foo<T>(T);").is_err());
+        assert!(parse("struct Foo(u8, u8);").is_err());
+        assert!(parse("struct Foo(pub u8);").is_err());
+        assert!(parse("enum Foo { A = 1 << 2 }").is_err());
+        assert!(parse("enum Foo { A(u8) = 1 }").is_err());
+        assert!(parse("enum Foo { A { x: u8 } }").is_err());
+        assert!(parse("type Foo = [u8; 4];").is_err());
+        assert!(parse("struct Foo { x: serde_json::value }").is_err());
+        assert!(parse("struct Unit;").is_err());
+        assert!(parse("struct Foo { a: &str }").is_err());
+        assert!(parse("struct Foo(Vec<&str>);").is_err());
+        assert!(parse("enum Foo { A(Option<&str>) }").is_err());
+        assert!(parse("type Foo = &u8;").is_err());
+        assert!(parse("struct Foo { b: HashMap<u32, String> }").is_err());
+        assert!(parse("struct Foo { b: BTreeMap<String, Map<i64, u8>> }").is_err());
+        assert!(parse("struct Foo { a: Cow<'static, str> }").is_err());
+        assert!(parse("struct Foo { a: outer::Vec<u8>::Inner }").is_err());
+        assert!(parse("struct Foo { a: Wrapper<&u8> }").is_err());
+        // consts may elide the 'static
+        assert!(parse("const NAME: &str = \"name\";").is_ok());
+    }
+}
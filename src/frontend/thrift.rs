@@ -0,0 +1,510 @@
+//! Apache Thrift IDL frontend
+//!
+//! Items are mapped onto the crate model as follows:
+//!
+//! * `struct` and `exception` become a `Struct`
+//! * `union` becomes an `Enum` with one newtype variant per field
+//! * `enum` becomes an `Enum` of unit variants, keeping explicit values
+//! * `typedef` becomes an `Alias`
+//! * `const` becomes a `Const` (scalar, string and enum constants only)
+//!
+//! `optional` fields are wrapped in `Type::Option`, `set<T>` is treated as
+//! `list<T>` and `binary` as `Vec<u8>`. Services and namespaces are skipped.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use errors::*;
+use frontend::lexer::{tokenize, Token, Tokens};
+use {Alias, Attributes, Const, Disambiguation, Enum, Field, Id, Item, ItemMap, NamingPolicy,
+     Primitive, Struct, Type, TypeId, Variant, Visibility};
+
+/// Parse a single Thrift document. `include`s are not followed,
+/// but types qualified with an include prefix (`shared.Thing`)
+/// are resolved by their bare name.
+pub fn parse(source: &str) -> Result<ItemMap> {
+    ItemMap::build(parse_document(source)?.items)
+}
+
+/// Parse a Thrift file and everything it (transitively) includes
+/// into a single `ItemMap`. Include paths are relative to the
+/// including file.
+pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<ItemMap> {
+    let mut seen = BTreeSet::new();
+    let mut items = Vec::new();
+    load_file(path.as_ref(), &mut seen, &mut items)?;
+    ItemMap::build(items)
+}
+
+fn load_file(path: &Path, seen: &mut BTreeSet<PathBuf>, items: &mut Vec<Box<Item>>) -> Result<()> {
+    let canonical = fs::canonicalize(path).chain_err(
+        || format!("Failed to find {}", path.display()),
+    )?;
+    if !seen.insert(canonical.clone()) {
+        return Ok(());
+    }
+    let source = fs::read_to_string(&canonical).chain_err(|| {
+        format!("Failed to read {}", path.display())
+    })?;
+    let doc = parse_document(&source).chain_err(
+        || format!("Failed to parse {}", path.display()),
+    )?;
+    let dir = canonical.parent().map(Path::to_path_buf).unwrap_or_default();
+    for include in doc.includes {
+        load_file(&dir.join(include), seen, items)?;
+    }
+    items.extend(doc.items);
+    Ok(())
+}
+
+struct Document {
+    includes: Vec<String>,
+    items: Vec<Box<Item>>,
+    /// Typedefs seen so far, by Rust name
+    typedefs: BTreeMap<String, Type>,
+    /// Enums seen so far, by Rust name
+    enums: BTreeMap<String, EnumValues>,
+}
+
+/// The Rust name of an enum and each variant's Thrift name, value
+/// and Rust name, to resolve constants of the enum type
+struct EnumValues {
+    name: Id,
+    variants: Vec<(String, i64, Id)>,
+}
+
+fn parse_document(source: &str) -> Result<Document> {
+    let mut toks = tokenize(source, true)?;
+    let mut doc = Document {
+        includes: Vec::new(),
+        items: Vec::new(),
+        typedefs: BTreeMap::new(),
+        enums: BTreeMap::new(),
+    };
+    while !toks.is_empty() {
+        let line = toks.line();
+        let keyword = toks.expect_ident()?;
+        match &*keyword {
+            "include" => doc.includes.push(toks.expect_str()?),
+            "cpp_include" => {
+                toks.expect_str()?;
+            }
+            "namespace" => {
+                if !toks.eat_punct('*') {
+                    toks.expect_ident()?;
+                }
+                toks.expect_ident()?;
+            }
+            "struct" | "exception" => doc.items.push(Box::new(parse_struct(&mut toks)?)),
+            "union" => doc.items.push(Box::new(parse_union(&mut toks)?)),
+            "enum" => {
+                let thrift_name = toks.expect_ident()?;
+                let (enm, values) = parse_enum(&thrift_name, &mut toks)?;
+                doc.enums.insert(values.name.to_string(), values);
+                doc.items.push(Box::new(enm));
+            }
+            "typedef" => {
+                let typ = parse_type(&mut toks)?;
+                skip_annotations(&mut toks)?;
+                let thrift_name = toks.expect_ident()?;
+                let name = type_name(&thrift_name)?;
+                skip_annotations(&mut toks)?;
+                doc.typedefs.insert(name.to_string(), typ.clone());
+                doc.items.push(Box::new(Alias::new(name, Visibility::Public, typ)));
+            }
+            "const" => {
+                let constant = parse_const(&mut toks, &doc)?;
+                doc.items.push(Box::new(constant));
+            }
+            "service" => {
+                while !toks.eat_punct('{') {
+                    toks.next()?;
+                }
+                toks.skip_group('{', '}')?;
+                skip_annotations(&mut toks)?;
+            }
+            other => bail!("line {}: unexpected '{}'", line, other),
+        }
+        eat_separator(&mut toks);
+    }
+    Ok(doc)
+}
+
+fn parse_struct(toks: &mut Tokens) -> Result<Struct> {
+    let name = type_name(&toks.expect_ident()?)?;
+    let fields = parse_fields(toks)?
+        .into_iter()
+        .map(|(name, typ, optional)| {
+            Field::with_rename(name, typ.optional(optional))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Struct::disambiguated(
+        name,
+        Visibility::Public,
+        Attributes::default(),
+        fields,
+        &Disambiguation::default(),
+    )
+}
+
+fn parse_union(toks: &mut Tokens) -> Result<Enum> {
+    let name = type_name(&toks.expect_ident()?)?;
+    let variants = parse_fields(toks)?
+        .into_iter()
+        .map(|(name, typ, _)| Variant::with_rename(name, Some(typ)))
+        .collect::<Result<Vec<_>>>()?;
+    Enum::disambiguated(
+        name,
+        Visibility::Public,
+        Attributes::default(),
+        variants,
+        &Disambiguation::default(),
+    )
+}
+
+/// Parse an enum, keeping explicit values as discriminants. As in Thrift,
+/// a variant without a value is one more than the previous variant.
+fn parse_enum(thrift_name: &str, toks: &mut Tokens) -> Result<(Enum, EnumValues)> {
+    let name = type_name(thrift_name)?;
+    toks.expect_punct('{')?;
+    let mut variants = Vec::new();
+    let mut thrift_values = Vec::new();
+    let mut next = 0;
+    while !toks.eat_punct('}') {
+        let line = toks.line();
+        let variant_name = toks.expect_ident()?;
+        let mut variant = Variant::with_rename(&variant_name, None)?;
+        if toks.eat_punct('=') {
+            let value = match toks.next()? {
+                Token::Number(ref n) => n.parse::<i64>().ok(),
+                _ => None,
+            };
+            next = match value {
+                Some(value) => value,
+                None => bail!("line {}: enum value of '{}' must be an integer", line, variant_name),
+            };
+            variant = variant.discriminant(next);
+        }
+        thrift_values.push((variant_name, next));
+        next += 1;
+        variants.push(variant);
+        skip_annotations(toks)?;
+        eat_separator(toks);
+    }
+    skip_annotations(toks)?;
+    let enm = Enum::disambiguated(
+        name.clone(),
+        Visibility::Public,
+        Attributes::default(),
+        variants,
+        &Disambiguation::default(),
+    )?;
+    // names may have changed in disambiguation
+    let variants = thrift_values
+        .into_iter()
+        .zip(enm.variants.iter())
+        .map(|((thrift_name, value), variant)| (thrift_name, value, variant.name.clone()))
+        .collect();
+    Ok((enm, EnumValues { name: name.into(), variants }))
+}
+
+/// The Rust value of a constant of an enum type, given either the
+/// variant's number or its name, e.g. `Status.DONE`
+fn enum_constant(values: &EnumValues, token: &Token) -> Option<String> {
+    values
+        .variants
+        .iter()
+        .find(|(thrift_name, value, _)| match token {
+            Token::Number(n) => n.parse() == Ok(*value),
+            Token::Ident(ident) => ident.rsplit('.').next() == Some(&**thrift_name),
+            _ => false,
+        })
+        .map(|(_, _, variant)| format!("{}::{}", values.name, variant))
+}
+
+fn parse_const(toks: &mut Tokens, doc: &Document) -> Result<Const> {
+    let typ = parse_type(toks)?;
+    let line = toks.line();
+    let name = NamingPolicy::default().const_name(&toks.expect_ident()?)?;
+    toks.expect_punct('=')?;
+    // look through typedefs to the type of the value
+    let mut value_type = &typ;
+    while let Type::Named(ref id) = *value_type {
+        match doc.typedefs.get(&**id) {
+            Some(target) => value_type = target,
+            None => break,
+        }
+    }
+    let token = toks.next()?;
+    let is_string = *value_type == Type::Primitive(Primitive::String);
+    if let Type::Named(ref id) = *value_type {
+        let value = doc.enums.get(&**id).and_then(|values| enum_constant(values, &token));
+        return match value {
+            Some(value) => Ok(Const::new(name, Visibility::Public, typ.clone(), value)),
+            None => bail!(
+                "line {}: constant '{}' = {:?} is not a variant of a known enum {}",
+                line,
+                name,
+                token,
+                id
+            ),
+        };
+    }
+    let value = match (token, value_type) {
+        (Token::Str(s), &Type::Primitive(Primitive::String)) => format!("{:?}", s),
+        (Token::Number(ref n), &Type::Primitive(Primitive::Boolean)) if n == "0" => {
+            "false".into()
+        }
+        (Token::Number(ref n), &Type::Primitive(Primitive::Boolean)) if n == "1" => {
+            "true".into()
+        }
+        (Token::Ident(ref b), &Type::Primitive(Primitive::Boolean))
+            if b == "true" || b == "false" => b.clone(),
+        (Token::Number(n), &Type::Primitive(Primitive::F64)) => {
+            if n.contains('.') || n.contains('e') || n.contains('E') {
+                n
+            } else {
+                format!("{}.0", n)
+            }
+        }
+        (Token::Number(n), &Type::Primitive(_)) => n,
+        (value, _) => {
+            bail!(
+                "line {}: unsupported constant '{}' of type {} = {:?}",
+                line,
+                name,
+                typ,
+                value
+            )
+        }
+    };
+    // a literal is a `&str`, so neither `String` nor an alias of it will do
+    let typ = if is_string {
+        Type::Ref(None, Box::new(Type::Primitive(Primitive::Str)))
+    } else {
+        typ
+    };
+    Ok(Const::new(name, Visibility::Public, typ, value))
+}
+
+/// Parse a braced field list as `(name, type, is_optional)`
+fn parse_fields(toks: &mut Tokens) -> Result<Vec<(String, Type, bool)>> {
+    toks.expect_punct('{')?;
+    let mut fields = Vec::new();
+    while !toks.eat_punct('}') {
+        if let Some(&Token::Number(_)) = toks.peek() {
+            toks.next()?;
+            toks.expect_punct(':')?;
+        }
+        let optional = toks.eat_ident("optional");
+        toks.eat_ident("required");
+        let typ = parse_type(toks)?;
+        let name = toks.expect_ident()?;
+        if toks.eat_punct('=') {
+            skip_value(toks)?;
+        }
+        skip_annotations(toks)?;
+        eat_separator(toks);
+        fields.push((name, typ, optional));
+    }
+    skip_annotations(toks)?;
+    Ok(fields)
+}
+
+fn parse_type(toks: &mut Tokens) -> Result<Type> {
+    use Primitive::*;
+    let name = toks.expect_ident()?;
+    let typ = match &*name {
+        "bool" => Type::Primitive(Boolean),
+        "byte" | "i8" => Type::Primitive(I8),
+        "i16" => Type::Primitive(I16),
+        "i32" => Type::Primitive(I32),
+        "i64" => Type::Primitive(I64),
+        "double" => Type::Primitive(F64),
+        "string" => Type::Primitive(String),
+        "binary" => Type::Vec(Box::new(Type::Primitive(U8))),
+        "list" | "set" => {
+            toks.expect_punct('<')?;
+            let inner = parse_type(toks)?;
+            toks.expect_punct('>')?;
+            Type::Vec(Box::new(inner))
+        }
+        "map" => {
+            toks.expect_punct('<')?;
+            let key = parse_type(toks)?;
+            toks.expect_punct(',')?;
+            let value = parse_type(toks)?;
+            toks.expect_punct('>')?;
+            Type::Map(Box::new(key), Box::new(value))
+        }
+        other => {
+            // strip any include prefix
+            let bare = other.rsplit('.').next().unwrap_or(other);
+            Type::Named(type_name(bare)?.into())
+        }
+    };
+    skip_annotations(toks)?;
+    Ok(typ)
+}
+
+/// The Rust name of a struct, union, enum or typedef, converted the
+/// same way at its definition and at every reference to it
+fn type_name(name: &str) -> Result<TypeId> {
+    NamingPolicy::default().type_name(name)
+}
+
+fn skip_value(toks: &mut Tokens) -> Result<()> {
+    match toks.next()? {
+        Token::Punct('[') => toks.skip_group('[', ']'),
+        Token::Punct('{') => toks.skip_group('{', '}'),
+        _ => Ok(()),
+    }
+}
+
+fn skip_annotations(toks: &mut Tokens) -> Result<()> {
+    if toks.eat_punct('(') {
+        toks.skip_group('(', ')')?;
+    }
+    Ok(())
+}
+
+fn eat_separator(toks: &mut Tokens) {
+    if !toks.eat_punct(',') {
+        toks.eat_punct(';');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::rendered;
+
+    #[test]
+    fn test_parse_thrift() {
+        let src = r#"
+            include "shared.thrift"
+            namespace rs my.service
+
+            const i32 MAX_ITEMS = 100
+            const string GREETING = "hi"
+
+            typedef i64 Timestamp
+
+            enum Status {
+                IN_PROGRESS = 1,
+                DONE = 2 (deprecated = "no")
+            }
+
+            struct Order {
+                1: required i32 id,
+                2: optional string customerName;
+                3: list<shared.Item> items = [],
+                4: map<string, double> prices
+                5: Status status
+            }
+
+            union Payment {
+                1: string card
+                2: binary voucher
+            }
+
+            exception NotFound {
+                1: string message
+            }
+
+            const Status DEFAULT_STATUS = 2
+            const Status INITIAL_STATUS = Status.IN_PROGRESS
+            typedef i32 Count
+            const Count NO_ITEMS = 0
+            typedef string Name
+            const Name DEFAULT_NAME = "anonymous"
+
+            enum Level {
+                LOW,
+                HIGH = 10,
+                HIGHER
+            }
+
+            service Orders extends shared.Base {
+                Order get(1: i32 id) throws (1: NotFound nf)
+            }
+        "#;
+        let map = parse(src).unwrap();
+        let render = |name: &str| {
+            rendered(map.get(&Id::new(name).unwrap()).unwrap())
+        };
+        assert_eq!(render("MAX_ITEMS"), "pub const MAX_ITEMS: i32 = 100;\n");
+        assert_eq!(render("GREETING"), "pub const GREETING: &str = \"hi\";\n");
+        assert_eq!(render("Timestamp"), "pub type Timestamp = i64;\n");
+        assert_eq!(
+            render("Status"),
+            r#"pub enum Status {
+    #[serde(rename = "IN_PROGRESS")]
+    InProgress = 1,
+    #[serde(rename = "DONE")]
+    Done = 2,
+}
+"#
+        );
+        assert_eq!(render("DEFAULT_STATUS"), "pub const DEFAULT_STATUS: Status = Status::Done;\n");
+        assert_eq!(
+            render("INITIAL_STATUS"),
+            "pub const INITIAL_STATUS: Status = Status::InProgress;\n"
+        );
+        assert_eq!(render("NO_ITEMS"), "pub const NO_ITEMS: Count = 0;\n");
+        assert_eq!(render("DEFAULT_NAME"), "pub const DEFAULT_NAME: &str = \"anonymous\";\n");
+        assert_eq!(
+            render("Level"),
+            r#"pub enum Level {
+    #[serde(rename = "LOW")]
+    Low,
+    #[serde(rename = "HIGH")]
+    High = 10,
+    #[serde(rename = "HIGHER")]
+    Higher,
+}
+"#
+        );
+        assert_eq!(
+            render("Order"),
+            r#"pub struct Order {
+    id: i32,
+    #[serde(rename = "customerName")]
+    customer_name: Option<String>,
+    items: Vec<Item>,
+    prices: Map<String, f64>,
+    status: Status,
+}
+"#
+        );
+        assert_eq!(
+            render("Payment"),
+            r#"pub enum Payment {
+    #[serde(rename = "card")]
+    Card(String),
+    #[serde(rename = "voucher")]
+    Voucher(Vec<u8>),
+}
+"#
+        );
+        assert_eq!(
+            render("NotFound"),
+            "pub struct NotFound {\n    message: String,\n}\n"
+        );
+        assert!(map.get(&Id::new("Orders").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_parse_thrift_errors() {
+        assert!(parse("struct Foo { 1: i32 a ").is_err());
+        assert!(parse("const list<i32> XS = [1, 2]").is_err());
+        assert!(parse("struct Foo {} struct Foo {}").is_err());
+        assert!(parse("bogus Foo {}").is_err());
+        // enum constants must name a variant of an enum defined earlier
+        assert!(parse("const Status S = 1").is_err());
+        assert!(parse("enum Status { A = 1 } const Status S = 2").is_err());
+        assert!(parse("enum Status { A = 1, B = 1 }").is_err());
+        assert!(parse("enum Status { A = X }").is_err());
+    }
+}
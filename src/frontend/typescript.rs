@@ -0,0 +1,380 @@
+//! TypeScript declaration (`.d.ts`) frontend
+//!
+//! Supports a subset of the type language:
+//!
+//! * `interface`s (including `extends`) become a `Struct`
+//! * unions of string literals become an `Enum` of unit variants
+//! * other `type` aliases become an `Alias`, or a `Struct` for object literals
+//! * optional `?` properties and `T | null` / `T | undefined` become `Type::Option`
+//! * `T[]` and `Array<T>` become `Vec<T>`, `Record<string, T>` becomes `Map<String, T>`
+//! * `number` becomes `f64`, and `any`/`unknown` become `Value`,
+//!   which must be in scope (e.g. `use serde_json::Value`)
+//!
+//! Inline object types and literal unions are given their own item, named
+//! after their parent and the property they appear in (`Order` + `status` =
+//! `OrderStatus`). Generic declarations are not supported.
+
+use std::collections::BTreeMap;
+
+use inflector::Inflector;
+
+use errors::*;
+use frontend::lexer::{tokenize, Token, Tokens};
+use {Alias, Attributes, Disambiguation, Enum, Field, Id, Item, ItemMap, Struct, Type,
+     TypeId, Variant, Visibility};
+
+/// Parse TypeScript declarations into an `ItemMap`
+pub fn parse(source: &str) -> Result<ItemMap> {
+    let mut toks = tokenize(source, false)?;
+    let mut importer = Importer::default();
+    while !toks.is_empty() {
+        let line = toks.line();
+        toks.eat_ident("export");
+        toks.eat_ident("declare");
+        let keyword = toks.expect_ident()?;
+        match &*keyword {
+            "interface" => {
+                let name = toks.expect_ident()?;
+                let mut parents = Vec::new();
+                if toks.eat_ident("extends") {
+                    loop {
+                        parents.push(toks.expect_ident()?);
+                        if !toks.eat_punct(',') {
+                            break;
+                        }
+                    }
+                }
+                if toks.peek_punct('<') {
+                    bail!("line {}: generic interface '{}' is not supported", line, name)
+                }
+                toks.expect_punct('{')?;
+                let members = parse_members(&mut toks)?;
+                let strukt = importer.object(members, &name)?;
+                importer.interfaces.insert(name, (parents, strukt));
+            }
+            "type" => {
+                let name = toks.expect_ident()?;
+                if toks.peek_punct('<') {
+                    bail!("line {}: generic type '{}' is not supported", line, name)
+                }
+                toks.expect_punct('=')?;
+                let ts = parse_type(&mut toks)?;
+                let id = type_id(&name)?;
+                let typ = importer.resolve(ts, &name)?;
+                if typ != Type::Named(id.clone().into()) {
+                    importer.items.push(Box::new(Alias::new(id, Visibility::Public, typ)));
+                }
+            }
+            other => bail!("line {}: unsupported declaration '{}'", line, other),
+        }
+        toks.eat_punct(';');
+    }
+    let names: Vec<String> = importer.interfaces.keys().cloned().collect();
+    for name in names {
+        let strukt = importer.flatten(&name, &mut Vec::new())?;
+        importer.items.push(Box::new(strukt));
+    }
+    ItemMap::build(importer.items)
+}
+
+/// A parsed but not yet resolved TypeScript type
+enum TsType {
+    Literal(String),
+    Null,
+    Named(String),
+    Object(Vec<Member>),
+    Union(Vec<TsType>),
+    Array(Box<TsType>),
+    Record(Box<TsType>),
+}
+
+struct Member {
+    name: String,
+    optional: bool,
+    typ: TsType,
+}
+
+/// Parse object members, assuming the opening brace has been consumed
+fn parse_members(toks: &mut Tokens) -> Result<Vec<Member>> {
+    let mut members = Vec::new();
+    while !toks.eat_punct('}') {
+        toks.eat_ident("readonly");
+        let line = toks.line();
+        let name = match toks.next()? {
+            Token::Ident(name) | Token::Str(name) => name,
+            other => bail!("line {}: expected property name, found {:?}", line, other),
+        };
+        let optional = toks.eat_punct('?');
+        toks.expect_punct(':')?;
+        let typ = parse_type(toks)?;
+        members.push(Member {
+            name,
+            optional,
+            typ,
+        });
+        if !toks.eat_punct(';') {
+            toks.eat_punct(',');
+        }
+    }
+    Ok(members)
+}
+
+fn parse_type(toks: &mut Tokens) -> Result<TsType> {
+    toks.eat_punct('|');
+    let mut variants = vec![parse_primary(toks)?];
+    while toks.eat_punct('|') {
+        variants.push(parse_primary(toks)?);
+    }
+    if variants.len() == 1 {
+        Ok(variants.pop().unwrap())
+    } else {
+        Ok(TsType::Union(variants))
+    }
+}
+
+fn parse_primary(toks: &mut Tokens) -> Result<TsType> {
+    let line = toks.line();
+    let mut typ = match toks.next()? {
+        Token::Str(s) => TsType::Literal(s),
+        Token::Punct('{') => TsType::Object(parse_members(toks)?),
+        Token::Punct('(') => {
+            let inner = parse_type(toks)?;
+            toks.expect_punct(')')?;
+            inner
+        }
+        Token::Ident(ref s) if s == "null" || s == "undefined" => TsType::Null,
+        Token::Ident(ref s) if s == "Array" => {
+            toks.expect_punct('<')?;
+            let inner = parse_type(toks)?;
+            toks.expect_punct('>')?;
+            TsType::Array(Box::new(inner))
+        }
+        Token::Ident(ref s) if s == "Record" => {
+            toks.expect_punct('<')?;
+            if !toks.eat_ident("string") {
+                bail!("line {}: only Record<string, T> is supported", line)
+            }
+            toks.expect_punct(',')?;
+            let inner = parse_type(toks)?;
+            toks.expect_punct('>')?;
+            TsType::Record(Box::new(inner))
+        }
+        Token::Ident(s) => {
+            if toks.peek_punct('<') {
+                bail!("line {}: generic type '{}' is not supported", line, s)
+            }
+            TsType::Named(s)
+        }
+        other => bail!("line {}: expected a type, found {:?}", line, other),
+    };
+    while toks.eat_punct('[') {
+        toks.expect_punct(']')?;
+        typ = TsType::Array(Box::new(typ));
+    }
+    Ok(typ)
+}
+
+#[derive(Default)]
+struct Importer {
+    items: Vec<Box<Item>>,
+    /// Interfaces with their parents, flattened once everything is parsed
+    interfaces: BTreeMap<String, (Vec<String>, Struct)>,
+}
+
+impl Importer {
+    /// Convert a parsed type into the model. Object types and literal
+    /// unions create an item called `name` and return a reference to it.
+    fn resolve(&mut self, ts: TsType, name: &str) -> Result<Type> {
+        use Primitive::*;
+        let typ = match ts {
+            TsType::Named(ref s) if s == "string" => Type::Primitive(String),
+            TsType::Named(ref s) if s == "number" => Type::Primitive(F64),
+            TsType::Named(ref s) if s == "boolean" => Type::Primitive(Boolean),
+            TsType::Named(ref s) if s == "any" || s == "unknown" => Type::named("Value")?,
+            TsType::Named(s) => Type::Named(type_id(&s)?.into()),
+            TsType::Null => Type::Primitive(Null),
+            TsType::Array(inner) => Type::Vec(Box::new(self.resolve(*inner, name)?)),
+            TsType::Record(inner) => {
+                Type::Map(
+                    Box::new(Type::Primitive(String)),
+                    Box::new(self.resolve(*inner, name)?),
+                )
+            }
+            TsType::Object(members) => {
+                let strukt = self.object(members, name)?;
+                let id = strukt.name.clone();
+                self.items.push(Box::new(strukt));
+                Type::Named(id)
+            }
+            TsType::Literal(lit) => self.literals(vec![lit], name)?,
+            TsType::Union(variants) => {
+                let mut nullable = false;
+                let mut literals = Vec::new();
+                let mut others = Vec::new();
+                for variant in variants {
+                    match variant {
+                        TsType::Null => nullable = true,
+                        TsType::Literal(lit) => literals.push(lit),
+                        other => others.push(other),
+                    }
+                }
+                let typ = match (literals.is_empty(), others.len()) {
+                    (false, 0) => self.literals(literals, name)?,
+                    (true, 1) => self.resolve(others.pop().unwrap(), name)?,
+                    _ => bail!("union '{}' must be of string literals or nullable", name),
+                };
+                typ.optional(nullable)
+            }
+        };
+        Ok(typ)
+    }
+
+    fn object(&mut self, members: Vec<Member>, name: &str) -> Result<Struct> {
+        let id = type_id(name)?;
+        let mut fields = Vec::new();
+        for member in members {
+            let child = format!("{}{}", id, member.name.to_pascal_case());
+            let typ = match self.resolve(member.typ, &child)? {
+                typ @ Type::Option(_) => typ,
+                typ => typ.optional(member.optional),
+            };
+            fields.push(Field::with_rename(member.name, typ)?);
+        }
+        Struct::disambiguated(
+            id,
+            Visibility::Public,
+            Attributes::default(),
+            fields,
+            &Disambiguation::default(),
+        )
+    }
+
+    fn literals(&mut self, literals: Vec<String>, name: &str) -> Result<Type> {
+        let id = type_id(name)?;
+        let variants = literals
+            .iter()
+            .map(|lit| Variant::with_rename(lit, None))
+            .collect::<Result<Vec<_>>>()?;
+        self.items.push(Box::new(Enum::disambiguated(
+            id.clone(),
+            Visibility::Public,
+            Attributes::default(),
+            variants,
+            &Disambiguation::default(),
+        )?));
+        Ok(Type::Named(id.into()))
+    }
+
+    /// Merge the fields of all parent interfaces into `name`
+    fn flatten(&self, name: &str, stack: &mut Vec<String>) -> Result<Struct> {
+        if stack.iter().any(|s| s == name) {
+            bail!("interface '{}' extends itself", name)
+        }
+        let (parents, strukt) = match self.interfaces.get(name) {
+            Some(entry) => entry,
+            None => bail!("unknown interface '{}'", name),
+        };
+        if parents.is_empty() {
+            return Ok(strukt.clone());
+        }
+        stack.push(name.into());
+        let mut structs = parents
+            .iter()
+            .map(|parent| self.flatten(parent, stack))
+            .collect::<Result<Vec<_>>>()?;
+        stack.pop();
+        structs.push(strukt.clone());
+        Struct::merge(
+            TypeId::from_id(strukt.name.clone())?,
+            strukt.vis,
+            strukt.attrs.clone(),
+            &structs,
+        )
+    }
+}
+
+fn type_id(name: &str) -> Result<TypeId> {
+    TypeId::from_id(Id::make_valid(name.to_pascal_case())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::rendered;
+
+    #[test]
+    fn test_parse_typescript() {
+        let src = r#"
+            /** A thing we sell */
+            export interface Entity {
+                readonly id: number;
+            }
+
+            export interface Order extends Entity {
+                customerName: string;
+                notes?: string | null;
+                lines: Array<OrderLine>;
+                "shipping-address"?: { street: string, zip: string };
+                status: 'pending' | 'in-progress' | 'done';
+                tags: Record<string, boolean>;
+            }
+
+            export type OrderLine = {
+                sku: string;
+                quantity: number;
+            };
+
+            type Sku = string[];
+            export type Color = | "red" | "green";
+        "#;
+        let map = parse(src).unwrap();
+        let render = |name: &str| {
+            rendered(map.get(&Id::new(name).unwrap()).unwrap())
+        };
+        assert_eq!(
+            render("Order"),
+            r#"pub struct Order {
+    id: f64,
+    #[serde(rename = "customerName")]
+    customer_name: String,
+    notes: Option<String>,
+    lines: Vec<OrderLine>,
+    #[serde(rename = "shipping-address")]
+    shipping_address: Option<OrderShippingAddress>,
+    status: OrderStatus,
+    tags: Map<String, bool>,
+}
+"#
+        );
+        assert_eq!(
+            render("OrderStatus"),
+            r#"pub enum OrderStatus {
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "in-progress")]
+    InProgress,
+    #[serde(rename = "done")]
+    Done,
+}
+"#
+        );
+        assert_eq!(
+            render("OrderShippingAddress"),
+            "pub struct OrderShippingAddress {\n    street: String,\n    zip: String,\n}\n"
+        );
+        assert_eq!(
+            render("OrderLine"),
+            "pub struct OrderLine {\n    sku: String,\n    quantity: f64,\n}\n"
+        );
+        assert_eq!(render("Sku"), "pub type Sku = Vec<String>;\n");
+        assert!(map.get(&Id::new("Color").unwrap()).is_some());
+    }
+
+    #[test]
+    fn test_parse_typescript_errors() {
+        assert!(parse("interface A<T> { a: T }").is_err());
+        assert!(parse("type A = string | number;").is_err());
+        assert!(parse("interface A extends B { a: string }").is_err());
+        assert!(parse("function foo(): void;").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! The graph of references between the items of an `ItemMap`
+
+use std::collections::BTreeMap;
+
+use analysis::strongly_connected;
+use {Id, ItemMap};
+
+/// Items and the types they refer to. References are counted wherever
+/// they appear within a type: map keys and values, both arms of a
+/// `Result`, tuple elements and so on. Referenced types which are not
+/// items (external or unresolved) are nodes without dependencies.
+pub struct DependencyGraph {
+    ids: Vec<Id>,
+    index: BTreeMap<Id, usize>,
+    items: usize,
+    deps: Vec<Vec<usize>>,
+    rdeps: Vec<Vec<usize>>,
+}
+
+impl DependencyGraph {
+    pub fn new(map: &ItemMap) -> DependencyGraph {
+        let mut ids: Vec<Id> = map.iter().map(|(id, _)| id.clone()).collect();
+        let mut index: BTreeMap<Id, usize> = ids.iter()
+            .enumerate()
+            .map(|(ix, id)| (id.clone(), ix))
+            .collect();
+        let items = ids.len();
+        let mut deps = vec![Vec::new(); items];
+        for (ix, (_, item)) in map.iter().enumerate() {
+            for id in item.get_named_types() {
+                let dep = match index.get(id) {
+                    Some(&dep) => dep,
+                    None => ids.len(),
+                };
+                if dep == ids.len() {
+                    ids.push(id.clone());
+                    index.insert(id.clone(), dep);
+                    deps.push(Vec::new());
+                }
+                if !deps[ix].contains(&dep) {
+                    deps[ix].push(dep);
+                }
+            }
+        }
+        let mut rdeps = vec![Vec::new(); ids.len()];
+        for (ix, node_deps) in deps.iter_mut().enumerate() {
+            node_deps.sort();
+            for &dep in node_deps.iter() {
+                rdeps[dep].push(ix);
+            }
+        }
+        DependencyGraph {
+            ids,
+            index,
+            items,
+            deps,
+            rdeps,
+        }
+    }
+
+    /// The ids directly referred to by `id`, ordered as in the map
+    /// with any types outside the map last
+    pub fn dependencies(&self, id: &Id) -> Vec<&Id> {
+        self.lookup(id, &self.deps)
+    }
+
+    /// The items which directly refer to `id`
+    pub fn dependents(&self, id: &Id) -> Vec<&Id> {
+        self.lookup(id, &self.rdeps)
+    }
+
+    /// The items of the map, with every item after the items it refers to.
+    /// Mutually recursive items are placed next to each other.
+    pub fn topological_order(&self) -> Vec<&Id> {
+        strongly_connected(&self.deps)
+            .into_iter()
+            .flat_map(|mut component| {
+                component.sort();
+                component
+            })
+            .filter(|&ix| ix < self.items)
+            .map(|ix| &self.ids[ix])
+            .collect()
+    }
+
+    /// Render the graph in Graphviz DOT format. Types which are not
+    /// items of the map are drawn dashed.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph items {\n");
+        for (ix, id) in self.ids.iter().enumerate() {
+            if ix < self.items {
+                out.push_str(&format!("    \"{}\";\n", id));
+            } else {
+                out.push_str(&format!("    \"{}\" [style=dashed];\n", id));
+            }
+        }
+        for (ix, node_deps) in self.deps.iter().enumerate() {
+            for &dep in node_deps {
+                out.push_str(&format!("    \"{}\" -> \"{}\";\n", self.ids[ix], self.ids[dep]));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn lookup(&self, id: &Id, edges: &[Vec<usize>]) -> Vec<&Id> {
+        match self.index.get(id) {
+            Some(&ix) => edges[ix].iter().map(|&ix| &self.ids[ix]).collect(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analysis::tests::{named, strukt};
+    use {Primitive, Type};
+
+    fn names(ids: Vec<&Id>) -> Vec<&str> {
+        ids.into_iter().map(|id| &**id).collect()
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let map = ItemMap::build(vec![
+            strukt(
+                "Order",
+                vec![
+                    ("lines", Type::Map(Box::new(named("Sku")), Box::new(named("Line")))),
+                    ("status", Type::Result(Box::new(named("Status")), Box::new(named("Error")))),
+                ],
+            ),
+            strukt("Line", vec![("price", Type::Tuple(vec![named("Sku"), named("Money")]))]),
+            strukt("Sku", vec![("code", Type::Primitive(Primitive::String))]),
+            strukt("Status", vec![("tree", Type::Box(Box::new(named("Tree"))))]),
+            strukt("Tree", vec![("children", Type::Vec(Box::new(named("Status"))))]),
+        ]).unwrap();
+        let graph = map.dependency_graph();
+        let id = |name: &str| Id::new(name).unwrap();
+
+        assert_eq!(
+            names(graph.dependencies(&id("Order"))),
+            vec!["Line", "Sku", "Status", "Error"]
+        );
+        assert_eq!(names(graph.dependencies(&id("Line"))), vec!["Sku", "Money"]);
+        assert_eq!(names(graph.dependents(&id("Sku"))), vec!["Line", "Order"]);
+        assert_eq!(names(graph.dependents(&id("Money"))), vec!["Line"]);
+        assert!(graph.dependencies(&id("Missing")).is_empty());
+        assert_eq!(
+            names(graph.topological_order()),
+            vec!["Sku", "Line", "Status", "Tree", "Order"]
+        );
+        let expect = r#"digraph items {
+    "Line";
+    "Order";
+    "Sku";
+    "Status";
+    "Tree";
+    "Money" [style=dashed];
+    "Error" [style=dashed];
+    "Line" -> "Sku";
+    "Line" -> "Money";
+    "Order" -> "Line";
+    "Order" -> "Sku";
+    "Order" -> "Status";
+    "Order" -> "Error";
+    "Status" -> "Tree";
+    "Tree" -> "Status";
+}
+"#;
+        assert_eq!(graph.to_dot(), expect);
+    }
+}
@@ -0,0 +1,282 @@
+//! Identifiers checked against the naming convention of what they name
+//!
+//! An `Id` only guarantees a valid identifier. `TypeId` and `ValueId`
+//! also check the case conventions the compiler lints for, so a type
+//! called `my_field` is caught when it is created rather than in the
+//! generated code. Both convert into an `Id` for building items.
+
+use std::fmt;
+use std::ops::Deref;
+
+use errors::*;
+use utils;
+use {Edition, Id, Type};
+
+/// The name of a struct, enum, newtype, alias, external type or enum
+/// variant, in UpperCamelCase
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TypeId(Id);
+
+impl TypeId {
+    pub fn new<I: Into<String>>(ident: I) -> Result<TypeId> {
+        TypeId::from_id(Id::new(ident)?)
+    }
+
+    /// Check the case of an existing `Id`
+    pub fn from_id(id: Id) -> Result<TypeId> {
+        if !utils::is_upper_camel_case(id.unraw()) {
+            bail!("Type name '{}' is not UpperCamelCase", id)
+        }
+        Ok(TypeId(id))
+    }
+}
+
+/// The name of a field, variable or const. Fields are snake_case and
+/// consts are SCREAMING_SNAKE_CASE.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ValueId(Id);
+
+impl ValueId {
+    /// A snake_case name, for a field
+    pub fn new<I: Into<String>>(ident: I) -> Result<ValueId> {
+        ValueId::from_id(Id::new(ident)?)
+    }
+
+    /// A SCREAMING_SNAKE_CASE name, for a const
+    pub fn constant<I: Into<String>>(ident: I) -> Result<ValueId> {
+        ValueId::constant_from_id(Id::new(ident)?)
+    }
+
+    /// Check that an existing `Id` is snake_case
+    pub fn from_id(id: Id) -> Result<ValueId> {
+        if !utils::is_snake_case(id.unraw()) {
+            bail!("Value name '{}' is not snake_case", id)
+        }
+        Ok(ValueId(id))
+    }
+
+    /// Check that an existing `Id` is SCREAMING_SNAKE_CASE
+    pub fn constant_from_id(id: Id) -> Result<ValueId> {
+        if !utils::is_screaming_snake_case(id.unraw()) {
+            bail!("Const name '{}' is not SCREAMING_SNAKE_CASE", id)
+        }
+        Ok(ValueId(id))
+    }
+}
+
+macro_rules! id_conversions {
+    ($typ:ident) => {
+        impl Deref for $typ {
+            type Target = Id;
+            fn deref(&self) -> &Id {
+                &self.0
+            }
+        }
+
+        impl From<$typ> for Id {
+            fn from(id: $typ) -> Id {
+                id.0
+            }
+        }
+
+        impl fmt::Display for $typ {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+id_conversions!(TypeId);
+id_conversions!(ValueId);
+
+/// A lifetime such as `'static` or `'a`, including the leading `'`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lifetime(String);
+
+impl Lifetime {
+    pub fn new<I: Into<String>>(lifetime: I) -> Result<Lifetime> {
+        let lifetime = lifetime.into();
+        {
+            let name = match lifetime.chars().next() {
+                Some('\'') => &lifetime[1..],
+                _ => bail!("Lifetime '{}' must start with a '", lifetime),
+            };
+            if name != "static" && name != "_" {
+                if utils::is_keyword(name, Edition::default()) || name.starts_with("r#") {
+                    bail!("Lifetime '{}' is a Rust keyword", lifetime)
+                }
+                utils::validate_identifier(name, Edition::default())
+                    .chain_err(|| format!("Invalid lifetime '{}'", lifetime))?;
+            }
+        }
+        Ok(Lifetime(lifetime))
+    }
+
+    /// The `'static` lifetime
+    pub fn static_() -> Lifetime {
+        Lifetime("'static".into())
+    }
+}
+
+impl fmt::Display for Lifetime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A path to a type outside the `ItemMap`, such as `serde_json::Value`
+/// or `::std::time::Duration`, optionally with generic arguments as in
+/// `chrono::DateTime<Utc>`. The last segment is a type and so must be
+/// UpperCamelCase. `crate`, `self` and `super` may lead the path.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path {
+    global: bool,
+    segments: Vec<Id>,
+    args: Vec<Type>,
+}
+
+const PATH_PREFIXES: &[&str] = &["crate", "self", "Self", "super"];
+
+impl Path {
+    pub fn new(path: &str) -> Result<Path> {
+        let global = path.starts_with("::");
+        let rest = if global { &path[2..] } else { path };
+        let mut segments = Vec::new();
+        for (ix, segment) in rest.split("::").enumerate() {
+            let segment = segment.trim();
+            let leading = segments.iter().all(|s: &Id| &**s == "super");
+            let allowed = match segment {
+                "super" => leading && !global,
+                "crate" | "self" | "Self" => ix == 0 && !global,
+                _ => false,
+            };
+            if allowed {
+                segments.push(Id(segment.into()));
+                continue;
+            }
+            if PATH_PREFIXES.contains(&segment) {
+                bail!("'{}' cannot appear there in path '{}'", segment, path)
+            }
+            let id = Id::new(segment).chain_err(|| format!("Invalid path '{}'", path))?;
+            segments.push(id);
+        }
+        {
+            let last = segments.last().unwrap();
+            if PATH_PREFIXES.contains(&&**last) {
+                bail!("Path '{}' does not name a type", path)
+            }
+            if !utils::is_upper_camel_case(last.unraw()) {
+                bail!("Type name '{}' in path '{}' is not UpperCamelCase", last, path)
+            }
+        }
+        Ok(Path {
+            global,
+            segments,
+            args: Vec::new(),
+        })
+    }
+
+    /// A path of a single segment, naming a type in scope
+    pub(crate) fn bare(name: &Id) -> Path {
+        Path {
+            global: false,
+            segments: vec![name.clone()],
+            args: Vec::new(),
+        }
+    }
+
+    /// Set the generic arguments, as in `chrono::DateTime<Utc>`
+    pub fn with_args(mut self, args: Vec<Type>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn segments(&self) -> &[Id] {
+        &self.segments
+    }
+
+    pub fn args(&self) -> &[Type] {
+        &self.args
+    }
+
+    pub(crate) fn args_mut(&mut self) -> &mut [Type] {
+        &mut self.args
+    }
+
+    /// The type at the end of the path
+    pub fn name(&self) -> &Id {
+        self.segments.last().unwrap()
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.global {
+            write!(f, "::")?;
+        }
+        let segments: Vec<&str> = self.segments.iter().map(|s| &**s).collect();
+        write!(f, "{}", segments.join("::"))?;
+        if !self.args.is_empty() {
+            let args: Vec<String> = self.args.iter().map(|arg| arg.to_string()).collect();
+            write!(f, "<{}>", args.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_ids() {
+        assert!(TypeId::new("HttpServer").is_ok());
+        assert!(TypeId::new("V1_0").is_ok());
+        assert!(TypeId::new("r#Type").is_ok());
+        assert!(TypeId::new("my_field").is_err());
+        assert!(TypeId::new("Http_Server").is_err());
+        assert!(TypeId::new("type").is_err());
+
+        assert!(ValueId::new("my_field").is_ok());
+        assert!(ValueId::new("r#type").is_ok());
+        assert!(ValueId::new("field2").is_ok());
+        assert!(ValueId::new("myField").is_err());
+        assert!(ValueId::new("my__field").is_err());
+        assert!(ValueId::constant("MAX_SIZE").is_ok());
+        assert!(ValueId::constant("MaxSize").is_err());
+        let id: Id = TypeId::new("Order").unwrap().into();
+        assert_eq!(id, Id::new("Order").unwrap());
+
+        assert!(Lifetime::new("'a").is_ok());
+        assert!(Lifetime::new("'_").is_ok());
+        assert_eq!(Lifetime::new("'static").unwrap(), Lifetime::static_());
+        assert!(Lifetime::new("a").is_err());
+        assert!(Lifetime::new("'fn").is_err());
+        assert!(Lifetime::new("'3").is_err());
+    }
+
+    #[test]
+    fn test_path() {
+        let path = Path::new("serde_json::Value").unwrap();
+        assert_eq!(path.to_string(), "serde_json::Value");
+        assert_eq!(&**path.name(), "Value");
+        let global = Path::new("::std::time::Duration").unwrap();
+        assert_eq!(global.to_string(), "::std::time::Duration");
+        assert!(Path::new("crate::models::Order").is_ok());
+        assert!(Path::new("super::super::Order").is_ok());
+        assert!(Path::new("Order").is_ok());
+        let utc = Type::named("Utc").unwrap();
+        let generic = Path::new("chrono::DateTime").unwrap().with_args(vec![utc.clone()]);
+        assert_eq!(generic.to_string(), "chrono::DateTime<Utc>");
+        assert_eq!(generic.args(), &[utc]);
+        assert_ne!(generic, Path::new("chrono::DateTime").unwrap());
+
+        assert!(Path::new("serde_json::value").is_err());
+        assert!(Path::new("models::crate::Order").is_err());
+        assert!(Path::new("::crate::Order").is_err());
+        assert!(Path::new("models::super").is_err());
+        assert!(Path::new("models::").is_err());
+        assert!(Path::new("my mod::Order").is_err());
+    }
+}
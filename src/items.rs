@@ -1,29 +1,267 @@
 use errors::*;
-use {Struct, Enum, NewType, Alias, Id};
+use analysis::{self, Analysis};
+use format::{self, SyntaxError};
+use graph::DependencyGraph;
+use render::RenderOptions;
+use {Struct, Enum, NewType, Alias, Const, Derive, Id, Path, Type, TypeId};
 
-use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::collections::{btree_map, BTreeMap};
 use std::fmt;
+use std::rc::Rc;
 
-pub struct ItemMap(BTreeMap<Id, Box<Item>>);
+pub struct ItemMap {
+    items: BTreeMap<Id, Box<Item>>,
+    /// Ids in the order the items were given to `build`
+    order: Vec<Id>,
+    externals: BTreeMap<Path, External>,
+    analysis: RefCell<Option<Rc<Analysis>>>,
+}
 
 impl ItemMap {
     pub fn build(items: Vec<Box<Item>>) -> Result<ItemMap> {
         let mut map = BTreeMap::new();
+        let mut order = Vec::new();
         for item in items {
             let name = item.name().clone();
+            order.push(name.clone());
             if let Some(item) = map.insert(name, item) {
                 bail!("None-unique Id: {}", item.name())
             }
         }
-        Ok(ItemMap(map))
+        Ok(ItemMap {
+            items: map,
+            order,
+            externals: BTreeMap::new(),
+            analysis: RefCell::new(None),
+        })
     }
 
     pub fn get(&self, id: &Id) -> Option<&Box<Item>> {
-        self.0.get(id)
+        self.items.get(id)
+    }
+
+    /// Declare a type which exists outside the map, so references to it
+    /// are not reported by `validate`. A `Named` reference resolves to an
+    /// external whose path is just that name. `Path` references are never
+    /// reported, but only derive what an external with exactly that path
+    /// (arguments included) declares.
+    pub fn declare_external(&mut self, external: External) -> Result<()> {
+        if external.path == Path::bare(external.path.name()) &&
+            self.items.contains_key(external.path.name())
+        {
+            bail!("External type '{}' is also an item", external.path)
+        }
+        self.externals.insert(external.path.clone(), external);
+        *self.analysis.get_mut() = None;
+        Ok(())
+    }
+
+    pub fn get_external(&self, path: &Path) -> Option<&External> {
+        self.externals.get(path)
+    }
+
+    /// Whether a `Named` or `Path` type is a declared external implementing
+    /// `derive`. A `Path` must match the external's path exactly, arguments
+    /// included.
+    pub(crate) fn external_derives(&self, typ: &Type, derive: &Derive) -> bool {
+        let external = match *typ {
+            Type::Named(ref id) => self.externals.get(&Path::bare(id)),
+            Type::Path(ref path) => self.externals.get(path),
+            _ => None,
+        };
+        external.map(|external| external.derives.contains(derive)).unwrap_or(false)
+    }
+
+    /// Every `Named` reference to a type which is neither an item
+    /// nor a declared external type
+    pub fn unresolved(&self) -> Vec<Unresolved> {
+        let mut unresolved = Vec::new();
+        for (id, item) in &self.items {
+            for (member, typ) in item.kind().members() {
+                for target in typ.named_ids() {
+                    if !self.items.contains_key(target) &&
+                        !self.externals.contains_key(&Path::bare(target))
+                    {
+                        unresolved.push(Unresolved {
+                            item: id.clone(),
+                            member: member.cloned(),
+                            target: target.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        unresolved
+    }
+
+    /// Check that every `Named` reference resolves, see `unresolved`
+    pub fn validate(&self) -> Result<()> {
+        let unresolved = self.unresolved();
+        if !unresolved.is_empty() {
+            let refs: Vec<String> = unresolved.iter().map(|u| u.to_string()).collect();
+            bail!("Unresolved type references: {}", refs.join(", "))
+        }
+        Ok(())
+    }
+
+    /// Items whose rendered output does not parse as Rust, or has a
+    /// malformed `serde` attribute, in the order the items were given to
+    /// `build`. Each item is checked on its own, so the positions are
+    /// within that item's rendering rather than the output of `render`.
+    /// See `check_syntax`.
+    pub fn syntax_errors(&self) -> Vec<SyntaxError> {
+        self.order
+            .iter()
+            .filter_map(|id| self.items[id].syntax_error())
+            .collect()
+    }
+
+    /// Check that every item renders to valid Rust, see `syntax_errors`
+    pub fn validate_syntax(&self) -> Result<()> {
+        let errors = self.syntax_errors();
+        if !errors.is_empty() {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            bail!(ErrorKind::Syntax(errors.join("\n")))
+        }
+        Ok(())
+    }
+
+    /// Iterate over the items, ordered by Id
+    pub fn iter(&self) -> btree_map::Iter<'_, Id, Box<Item>> {
+        self.items.iter()
+    }
+
+    /// The ids of the items, in the order they were given to `build`
+    pub fn insertion_order(&self) -> &[Id] {
+        &self.order
+    }
+
+    /// Render the whole map as the contents of a Rust source file
+    pub fn render(&self, options: &RenderOptions) -> String {
+        options.render(self)
+    }
+
+    /// Analyses of the whole map, computed on first use
+    pub fn analysis(&self) -> Rc<Analysis> {
+        let mut cached = self.analysis.borrow_mut();
+        if cached.is_none() {
+            *cached = Some(Rc::new(Analysis::new(self)));
+        }
+        cached.as_ref().unwrap().clone()
+    }
+
+    /// Put references behind a `Box` wherever needed for every item to
+    /// have a finite size, returning the changes made. Within each group of
+    /// mutually recursive items, references are kept inline where possible
+    /// and only those which would complete a cycle are boxed.
+    ///
+    /// Note that a cycle made only of type aliases cannot be fixed this way.
+    pub fn box_recursive(&mut self) -> Vec<Boxed> {
+        let mut changes = Vec::new();
+        for (id, member, target) in analysis::boxing_plan(self) {
+            let item = self.items.get_mut(&id).unwrap();
+            let (name, typ) = item.kind_mut().members().swap_remove(member);
+            typ.box_id(&target);
+            changes.push(Boxed {
+                item: id,
+                member: name.cloned(),
+                target,
+            });
+        }
+        if !changes.is_empty() {
+            *self.analysis.get_mut() = None;
+        }
+        changes
+    }
+
+    /// Add each of `derives` to every struct, enum and newtype which can
+    /// derive it, as decided by `Analysis::derivable`. A derive is only
+    /// added together with the derives it depends on (e.g. `Eq` needs
+    /// `PartialEq` to be requested too). Custom derives are always added.
+    /// Returns the derives which were withheld from each item.
+    pub fn apply_derives(&mut self, derives: &[Derive]) -> BTreeMap<Id, Vec<Derive>> {
+        let analysis = self.analysis();
+        let mut withheld = BTreeMap::new();
+        for (id, item) in &mut self.items {
+            let attrs = match item.kind_mut() {
+                ItemKindMut::Struct(s) => &mut s.attrs,
+                ItemKindMut::Enum(e) => &mut e.attrs,
+                ItemKindMut::NewType(n) => &mut n.attrs,
+                ItemKindMut::Alias(_) | ItemKindMut::Const(_) => continue,
+            };
+            let valid = analysis.derivable(id);
+            for derive in derives {
+                let applies = match *derive {
+                    Derive::Custom(_) => true,
+                    _ => {
+                        valid.contains(derive) &&
+                            derive.requires().iter().all(|req| derives.contains(req))
+                    }
+                };
+                if applies {
+                    attrs.derive.insert(derive.clone());
+                } else {
+                    withheld.entry(id.clone()).or_insert_with(Vec::new).push(derive.clone());
+                }
+            }
+        }
+        withheld
+    }
+
+    /// Merge items which are identical apart from their names, keeping the
+    /// first of each group in insertion order and pointing every reference
+    /// to the others at it. Items may refer to themselves or each other, so
+    /// e.g. two copies of the same linked list are merged. If `keep_aliases`
+    /// is set, the removed names remain as `Alias` items of the kept one.
+    /// Returns the removed names, each with the name it was merged into.
+    pub fn dedup(&mut self, keep_aliases: bool) -> BTreeMap<Id, Id> {
+        let mut merged = BTreeMap::new();
+        for group in analysis::duplicates(self) {
+            let mut group = group.into_iter();
+            let kept = group.next().unwrap();
+            for id in group {
+                merged.insert(id, kept.clone());
+            }
+        }
+        if merged.is_empty() {
+            return merged;
+        }
+        let rename = |id: &Id| merged.get(id).cloned();
+        for (id, kept) in &merged {
+            let removed = self.items.remove(id).unwrap();
+            if keep_aliases {
+                let vis = match removed.kind() {
+                    ItemKind::Struct(s) => s.vis,
+                    ItemKind::Enum(e) => e.vis,
+                    ItemKind::NewType(n) => n.vis,
+                    ItemKind::Alias(a) => a.vis,
+                    ItemKind::Const(c) => c.vis,
+                };
+                let name = TypeId::from_id(id.clone()).expect("only types are merged");
+                let alias = Alias::new(name, vis, Type::Named(kept.clone()));
+                self.items.insert(id.clone(), Box::new(alias));
+            }
+        }
+        if !keep_aliases {
+            self.order.retain(|id| !merged.contains_key(id));
+        }
+        for item in self.items.values_mut() {
+            for (_, typ) in item.kind_mut().members() {
+                typ.rename_ids(&rename);
+            }
+        }
+        *self.analysis.get_mut() = None;
+        merged
+    }
+
+    /// The graph of references between items
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::new(self)
     }
 
     pub fn find_named_types(&self) -> Vec<&Id> {
-        self.0
+        self.items
             .iter()
             .flat_map(|(id, item)| {
                 let mut v = item.get_named_types();
@@ -34,11 +272,160 @@ impl ItemMap {
     }
 }
 
+/// A type defined outside the `ItemMap`, such as `chrono::DateTime<Utc>`
+/// or a bare `DateTime` brought into scope with `use`, along with the
+/// traits it implements so that derives can be inferred for items which
+/// use it
+#[derive(Debug, Clone, PartialEq, Eq, new)]
+pub struct External {
+    path: Path,
+    derives: Vec<Derive>,
+}
+
+/// A `Named` reference to a type which does not exist, see `ItemMap::unresolved`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unresolved {
+    /// The item holding the reference
+    pub item: Id,
+    /// The field or variant holding the reference, None for
+    /// a NewType, Alias or Const
+    pub member: Option<Id>,
+    /// The missing type
+    pub target: Id,
+}
+
+impl fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.member {
+            Some(ref member) => write!(f, "{}.{} -> {}", self.item, member, self.target),
+            None => write!(f, "{} -> {}", self.item, self.target),
+        }
+    }
+}
+
+/// Borrowed view of the concrete type behind an `Item`
+pub enum ItemKind<'a> {
+    Struct(&'a Struct),
+    Enum(&'a Enum),
+    NewType(&'a NewType),
+    Alias(&'a Alias),
+    Const(&'a Const),
+}
+
+impl<'a> ItemKind<'a> {
+    /// The types held by the item, with the field or variant holding them
+    pub(crate) fn members(&self) -> Vec<(Option<&'a Id>, &'a Type)> {
+        match *self {
+            ItemKind::Struct(s) => s.fields.iter().map(|f| (Some(&f.name), &f.typ)).collect(),
+            ItemKind::Enum(e) => {
+                e.variants
+                    .iter()
+                    .filter_map(|v| v.typ.as_ref().map(|typ| (Some(&v.name), typ)))
+                    .collect()
+            }
+            ItemKind::NewType(n) => vec![(None, &n.typ)],
+            ItemKind::Alias(a) => vec![(None, &a.typ)],
+            ItemKind::Const(c) => vec![(None, &c.typ)],
+        }
+    }
+}
+
+/// Mutable view of the concrete type behind an `Item`
+pub enum ItemKindMut<'a> {
+    Struct(&'a mut Struct),
+    Enum(&'a mut Enum),
+    NewType(&'a mut NewType),
+    Alias(&'a mut Alias),
+    Const(&'a mut Const),
+}
+
+impl<'a> ItemKindMut<'a> {
+    pub(crate) fn name_mut(self) -> &'a mut Id {
+        match self {
+            ItemKindMut::Struct(s) => &mut s.name,
+            ItemKindMut::Enum(e) => &mut e.name,
+            ItemKindMut::NewType(n) => &mut n.name,
+            ItemKindMut::Alias(a) => &mut a.name,
+            ItemKindMut::Const(c) => &mut c.name,
+        }
+    }
+
+    /// As `ItemKind::members`, in the same order
+    pub(crate) fn members(self) -> Vec<(Option<&'a Id>, &'a mut Type)> {
+        match self {
+            ItemKindMut::Struct(s) => {
+                s.fields.iter_mut().map(|f| (Some(&f.name), &mut f.typ)).collect()
+            }
+            ItemKindMut::Enum(e) => {
+                e.variants
+                    .iter_mut()
+                    .filter_map(|v| {
+                        let name = &v.name;
+                        v.typ.as_mut().map(|typ| (Some(name), typ))
+                    })
+                    .collect()
+            }
+            ItemKindMut::NewType(n) => vec![(None, &mut n.typ)],
+            ItemKindMut::Alias(a) => vec![(None, &mut a.typ)],
+            ItemKindMut::Const(c) => vec![(None, &mut c.typ)],
+        }
+    }
+}
+
+/// A reference put behind a `Box` by `ItemMap::box_recursive`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Boxed {
+    /// The item which was changed
+    pub item: Id,
+    /// The field or variant which was changed, None for a NewType or Alias
+    pub member: Option<Id>,
+    /// The item which is now boxed
+    pub target: Id,
+}
+
+impl fmt::Display for Boxed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.member {
+            Some(ref member) => write!(f, "{}.{}: Box<{}>", self.item, member, self.target),
+            None => write!(f, "{}: Box<{}>", self.item, self.target),
+        }
+    }
+}
+
 pub trait Item: fmt::Display {
     fn name(&self) -> &Id;
-    fn is_defaultable(&self, &ItemMap) -> bool;
-    fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool;
-    fn get_named_types(&self) -> Vec<&Id>;
+    fn kind(&self) -> ItemKind<'_>;
+    fn kind_mut(&mut self) -> ItemKindMut<'_>;
+    /// `Named` and `Path` types which must implement `derive` for this
+    /// item to derive it, or None if it never can
+    fn derive_deps(&self, derive: &Derive) -> Option<Vec<&Type>>;
+    /// Ids which are stored inline in this item, rather than behind a pointer
+    fn unboxed_ids(&self) -> Vec<&Id>;
+    /// Every Id referred to by the item
+    fn get_named_types(&self) -> Vec<&Id> {
+        self.kind()
+            .members()
+            .into_iter()
+            .flat_map(|(_, typ)| typ.named_ids())
+            .collect()
+    }
+    /// Where the rendered item fails to parse as Rust, if it does
+    fn syntax_error(&self) -> Option<SyntaxError> {
+        format::check_syntax(&self.to_string()).err().map(|mut error| {
+            error.item = Some(self.name().clone());
+            error
+        })
+    }
+    /// See `Analysis::is_defaultable`
+    fn is_defaultable(&self, map: &ItemMap) -> bool {
+        map.analysis().is_defaultable(self.name())
+    }
+    fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool {
+        let analysis = map.analysis();
+        self.unboxed_ids().iter().any(|&child| {
+            child == id || analysis.contains_unboxed_id(child, id)
+        })
+    }
     fn is_recursive(&self, map: &ItemMap) -> bool {
         self.contains_unboxed_id(self.name(), map)
     }
@@ -48,18 +435,23 @@ impl Item for Struct {
     fn name(&self) -> &Id {
         &self.name
     }
-    fn is_defaultable(&self, map: &ItemMap) -> bool {
-        self.fields.iter().all(|field| field.is_defaultable(map))
+    fn kind(&self) -> ItemKind<'_> {
+        ItemKind::Struct(self)
     }
-    fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool {
-        self.fields.iter().any(
-            |field| field.contains_unboxed_id(id, map),
-        )
+    fn kind_mut(&mut self) -> ItemKindMut<'_> {
+        ItemKindMut::Struct(self)
     }
-    fn get_named_types(&self) -> Vec<&Id> {
+    fn derive_deps(&self, derive: &Derive) -> Option<Vec<&Type>> {
+        let mut deps = Vec::new();
+        for field in &self.fields {
+            deps.extend(field.derive_deps(derive)?);
+        }
+        Some(deps)
+    }
+    fn unboxed_ids(&self) -> Vec<&Id> {
         self.fields
             .iter()
-            .filter_map(|field| field.get_named_type())
+            .flat_map(|field| field.unboxed_ids())
             .collect()
     }
 }
@@ -68,17 +460,26 @@ impl Item for Enum {
     fn name(&self) -> &Id {
         &self.name
     }
-    fn is_defaultable(&self, _: &ItemMap) -> bool {
-        false
+    fn kind(&self) -> ItemKind<'_> {
+        ItemKind::Enum(self)
     }
-    fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool {
-        self.variants.iter().any(|v| v.contains_unboxed_id(id, map))
+    fn kind_mut(&mut self) -> ItemKindMut<'_> {
+        ItemKindMut::Enum(self)
     }
-    fn get_named_types(&self) -> Vec<&Id> {
-        self.variants
-            .iter()
-            .filter_map(|variant| variant.get_named_type())
-            .collect()
+    fn derive_deps(&self, derive: &Derive) -> Option<Vec<&Type>> {
+        if *derive == Derive::Default {
+            return None;
+        }
+        let mut deps = Vec::new();
+        for variant in &self.variants {
+            if let Some(ref typ) = variant.typ {
+                deps.extend(typ.derive_deps(derive)?);
+            }
+        }
+        Some(deps)
+    }
+    fn unboxed_ids(&self) -> Vec<&Id> {
+        self.variants.iter().flat_map(|v| v.unboxed_ids()).collect()
     }
 }
 
@@ -86,14 +487,17 @@ impl Item for NewType {
     fn name(&self) -> &Id {
         &self.name
     }
-    fn is_defaultable(&self, map: &ItemMap) -> bool {
-        self.typ.is_defaultable(map)
+    fn kind(&self) -> ItemKind<'_> {
+        ItemKind::NewType(self)
     }
-    fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool {
-        self.typ.contains_unboxed_id(id, map)
+    fn kind_mut(&mut self) -> ItemKindMut<'_> {
+        ItemKindMut::NewType(self)
     }
-    fn get_named_types(&self) -> Vec<&Id> {
-        self.typ.get_named_root().into_iter().collect()
+    fn derive_deps(&self, derive: &Derive) -> Option<Vec<&Type>> {
+        self.typ.derive_deps(derive)
+    }
+    fn unboxed_ids(&self) -> Vec<&Id> {
+        self.typ.unboxed_ids()
     }
 }
 
@@ -101,13 +505,34 @@ impl Item for Alias {
     fn name(&self) -> &Id {
         &self.name
     }
-    fn is_defaultable(&self, map: &ItemMap) -> bool {
-        self.typ.is_defaultable(map)
+    fn kind(&self) -> ItemKind<'_> {
+        ItemKind::Alias(self)
     }
-    fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool {
-        self.typ.contains_unboxed_id(id, map)
+    fn kind_mut(&mut self) -> ItemKindMut<'_> {
+        ItemKindMut::Alias(self)
     }
-    fn get_named_types(&self) -> Vec<&Id> {
-        self.typ.get_named_root().into_iter().collect()
+    fn derive_deps(&self, derive: &Derive) -> Option<Vec<&Type>> {
+        self.typ.derive_deps(derive)
+    }
+    fn unboxed_ids(&self) -> Vec<&Id> {
+        self.typ.unboxed_ids()
+    }
+}
+
+impl Item for Const {
+    fn name(&self) -> &Id {
+        &self.name
+    }
+    fn kind(&self) -> ItemKind<'_> {
+        ItemKind::Const(self)
+    }
+    fn kind_mut(&mut self) -> ItemKindMut<'_> {
+        ItemKindMut::Const(self)
+    }
+    fn derive_deps(&self, _derive: &Derive) -> Option<Vec<&Type>> {
+        None
+    }
+    fn unboxed_ids(&self) -> Vec<&Id> {
+        vec![]
     }
 }
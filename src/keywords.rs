@@ -1,53 +1,95 @@
-pub(crate) static RUST_KEYWORDS: &'static [&'static str] = &[
-    "as",
-    "break",
-    "crate",
-    "else",
-    "enum",
-    "extern",
-    "false",
-    "fn",
-    "for",
-    "if",
-    "impl",
-    "in",
-    "let",
-    "loop",
-    "match",
-    "mod",
-    "move",
-    "mut",
-    "pub",
-    "ref",
-    "return",
-    "static",
-    "self",
-    "Self",
-    "struct",
-    "super",
-    "true",
-    "trait",
-    "type",
-    "unsafe",
-    "use",
-    "while",
-    "continue",
-    "box",
-    "const",
-    "where",
-    "virtual",
-    "proc",
-    "alignof",
-    "become",
-    "offsetof",
-    "priv",
-    "pure",
-    "sizeof",
-    "typeof",
-    "unsized",
-    "yield",
-    "do",
-    "abstract",
-    "final",
-    "override",
+//! Rust keywords and the editions in which they apply
+
+/// A Rust edition. Later editions reserve more words, so an identifier
+/// which is valid for one edition is valid for every earlier edition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Edition {
+    E2015,
+    E2018,
+    E2021,
+    E2024,
+}
+
+impl Default for Edition {
+    /// The latest edition, so that generated code compiles on any edition
+    fn default() -> Edition {
+        Edition::E2024
+    }
+}
+
+/// How a keyword restricts its use as an identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeywordKind {
+    /// Has a meaning in the language and cannot be an identifier
+    Strict,
+    /// Unused by the language but reserved for the future. Cannot be
+    /// an identifier.
+    Reserved,
+    /// Only special in certain contexts and can be used as an identifier
+    Weak,
+}
+
+use self::Edition::*;
+use self::KeywordKind::*;
+
+/// Every keyword with its kind from a given edition onwards.
+/// A word may appear more than once if its kind changed.
+pub(crate) static RUST_KEYWORDS: &[(&str, KeywordKind, Edition)] = &[
+    ("as", Strict, E2015),
+    ("break", Strict, E2015),
+    ("const", Strict, E2015),
+    ("continue", Strict, E2015),
+    ("crate", Strict, E2015),
+    ("else", Strict, E2015),
+    ("enum", Strict, E2015),
+    ("extern", Strict, E2015),
+    ("false", Strict, E2015),
+    ("fn", Strict, E2015),
+    ("for", Strict, E2015),
+    ("if", Strict, E2015),
+    ("impl", Strict, E2015),
+    ("in", Strict, E2015),
+    ("let", Strict, E2015),
+    ("loop", Strict, E2015),
+    ("match", Strict, E2015),
+    ("mod", Strict, E2015),
+    ("move", Strict, E2015),
+    ("mut", Strict, E2015),
+    ("pub", Strict, E2015),
+    ("ref", Strict, E2015),
+    ("return", Strict, E2015),
+    ("self", Strict, E2015),
+    ("Self", Strict, E2015),
+    ("static", Strict, E2015),
+    ("struct", Strict, E2015),
+    ("super", Strict, E2015),
+    ("trait", Strict, E2015),
+    ("true", Strict, E2015),
+    ("type", Strict, E2015),
+    ("unsafe", Strict, E2015),
+    ("use", Strict, E2015),
+    ("where", Strict, E2015),
+    ("while", Strict, E2015),
+    ("async", Strict, E2018),
+    ("await", Strict, E2018),
+    ("dyn", Weak, E2015),
+    ("dyn", Strict, E2018),
+    ("abstract", Reserved, E2015),
+    ("become", Reserved, E2015),
+    ("box", Reserved, E2015),
+    ("do", Reserved, E2015),
+    ("final", Reserved, E2015),
+    ("macro", Reserved, E2015),
+    ("override", Reserved, E2015),
+    ("priv", Reserved, E2015),
+    ("typeof", Reserved, E2015),
+    ("unsized", Reserved, E2015),
+    ("virtual", Reserved, E2015),
+    ("yield", Reserved, E2015),
+    ("try", Reserved, E2018),
+    ("gen", Reserved, E2024),
+    ("macro_rules", Weak, E2015),
+    ("union", Weak, E2015),
+    ("raw", Weak, E2015),
+    ("safe", Weak, E2024),
 ];
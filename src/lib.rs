@@ -10,58 +10,147 @@
 
 #[macro_use]
 extern crate error_chain;
-extern crate rustfmt;
-extern crate tempdir;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate derive_new;
 extern crate inflector;
+extern crate unicode_xid;
+extern crate deunicode;
+#[macro_use]
+extern crate serde_json;
+extern crate syn;
+extern crate quote;
+extern crate proc_macro2;
+extern crate prettyplease;
 
+use std::borrow::Cow;
 use std::fmt;
 use std::collections::BTreeSet;
 
 use inflector::Inflector;
 
 mod keywords;
+mod ids;
 pub mod utils;
 pub mod items;
 mod typebuilder;
+pub mod analysis;
+pub mod graph;
+pub mod render;
+pub mod naming;
+pub mod format;
+mod pretty;
+pub mod frontend;
+pub mod emit;
 
 use errors::*;
+use pretty::{Doc, Layout, Writer};
 pub use typebuilder::{Type, Primitive};
-pub use items::{Item, ItemMap};
+pub use keywords::{Edition, KeywordKind};
+pub use ids::{Lifetime, Path, TypeId, ValueId};
+pub use items::{Boxed, External, Item, ItemKind, ItemKindMut, ItemMap, Unresolved};
+pub use analysis::Analysis;
+pub use graph::DependencyGraph;
+pub use render::{Order, RenderOptions};
+pub use naming::{Case, NamingPolicy};
+pub use format::{check_syntax, Backend, FormatOptions, Formatter, GroupImports, SyntaxError,
+                 TrailingComma};
 
 #[allow(unused_doc_comment)]
 pub mod errors {
     error_chain!{
        foreign_links {
            Io(::std::io::Error);
+           Json(::serde_json::Error);
+        }
+       errors {
+           Syntax(message: String) {
+               description("syntax error")
+               display("Syntax error: {}", message)
+           }
         }
     }
 }
 
 /// Wrapper around String which guarantees that
-/// the value can be used as a Rust identifier
+/// the value can be used as a Rust identifier.
+/// The value may be a raw identifier such as `r#type`.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Id(String);
 
 impl Id {
+    /// Create a new Id, which must not be a keyword in any edition
     pub fn new<I: Into<String>>(ident: I) -> Result<Id> {
+        Id::for_edition(ident, Edition::default())
+    }
+
+    /// As `new`, allowing words which only became keywords after `edition`
+    pub fn for_edition<I: Into<String>>(ident: I, edition: Edition) -> Result<Id> {
         let ident: String = ident.into();
-        utils::validate_identifier(&ident)?;
+        utils::validate_identifier(&ident, edition)?;
         Ok(Id(ident))
     }
 
     /// Create a new Id, possibly mangled to make it into a valid identifier
     pub fn make_valid<I: Into<String>>(ident: I) -> Result<Id> {
+        Id::make_valid_escaped(ident, KeywordEscape::Underscore)
+    }
+
+    /// As `make_valid`, choosing how keywords are escaped
+    pub fn make_valid_escaped<I: Into<String>>(ident: I, escape: KeywordEscape) -> Result<Id> {
+        Id::make_valid_for_edition(ident, escape, Edition::default())
+    }
+
+    /// As `make_valid_escaped`, escaping only the keywords of `edition`
+    pub fn make_valid_for_edition<I: Into<String>>(
+        ident: I,
+        escape: KeywordEscape,
+        edition: Edition,
+    ) -> Result<Id> {
+        let ident = ident.into();
+        if let std::borrow::Cow::Owned(id) =
+            utils::make_valid_identifier(&ident, escape, edition)?
+        {
+            Ok(Id(id))
+        } else {
+            Ok(Id(ident))
+        }
+    }
+
+    /// As `make_valid`, but transliterating any non-ASCII characters so
+    /// the result is plain ASCII
+    pub fn make_valid_ascii<I: Into<String>>(ident: I) -> Result<Id> {
         let ident = ident.into();
-        if let std::borrow::Cow::Owned(id) = utils::make_valid_identifier(&ident)? {
+        let escape = KeywordEscape::Underscore;
+        let edition = Edition::default();
+        if let std::borrow::Cow::Owned(id) =
+            utils::make_valid_ascii_identifier(&ident, escape, edition)?
+        {
             Ok(Id(id))
         } else {
             Ok(Id(ident))
         }
     }
+
+    /// The identifier without any `r#` prefix, as `serde` sees it
+    pub fn unraw(&self) -> &str {
+        if self.0.starts_with("r#") {
+            &self.0[2..]
+        } else {
+            &self.0
+        }
+    }
+}
+
+/// How `Id::make_valid_escaped` turns keywords into identifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordEscape {
+    /// Append an underscore, `type` becomes `type_`
+    Underscore,
+    /// Use a raw identifier, `type` becomes `r#type`. Keywords which
+    /// cannot be raw (`self`, `Self`, `super` and `crate`) get an underscore.
+    Raw,
 }
 
 impl std::ops::Deref for Id {
@@ -83,26 +172,26 @@ impl fmt::Display for Id {
 ///
 /// ```ignore
 /// let my_struct = Struct::new(
-///     Id::new("MyStruct").unwrap(),
+///     TypeId::new("MyStruct").unwrap(),
 ///     Visibility::Public,
 ///     Attributes::default().derive(&[Clone, Debug]).cfg(
 ///         &[Test, TargetOs("linux".into())],
 ///     ),
 ///     vec![Field::new(
-///             Id::new("field1").unwrap(),
+///             ValueId::new("field1").unwrap(),
 ///             Type::named("Type1").unwrap(),
 ///             Default::default()
 ///         ),
 ///         Field::new(
-///             Id::new("field2").unwrap(),
+///             ValueId::new("field2").unwrap(),
 ///             Type::Box(Box::new(Type::named("Type2").unwrap())),
 ///             vec![SerdeRename("Field-2".into()), SerdeDefault]
 ///         ),
 ///         Field::with_rename("Snake Case Me", Type::named("Type3").unwrap())
 ///             .unwrap(),
 ///     ],
-/// );
-/// println!("{}", rust_format(my_struct.to_string()).unwrap());
+/// ).unwrap();
+/// println!("{}", my_struct);
 /// // #[derive(Debug, Clone)]
 /// // #[cfg(test, target_os = "linux")]
 /// // pub struct MyStruct {
@@ -114,7 +203,7 @@ impl fmt::Display for Id {
 /// //     snake_case_me: Type3,
 /// // }
 /// ```
-#[derive(Debug, Clone, PartialEq, Eq, new)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Struct {
     name: Id,
     vis: Visibility,
@@ -123,37 +212,60 @@ pub struct Struct {
 }
 
 impl Struct {
-    pub fn merge(new_name: Id, vis: Visibility, attrs: Attributes, structs: &[Struct]) -> Result<Struct> {
-        let mut fields = Vec::new();
-        let mut field_chk = BTreeSet::new();
-        for s in structs {
-            for field in &s.fields {
-                if !field_chk.insert(field.name.clone()) {
-                    bail!("Duplicated field '{}'", field.name)
-                }
-                fields.push(field.clone());
-            }
+    /// Create a Struct, failing if two fields have the same name
+    pub fn new(
+        name: TypeId,
+        vis: Visibility,
+        attrs: Attributes,
+        fields: Vec<Field>,
+    ) -> Result<Struct> {
+        let name = Id::from(name);
+        if let Some(dup) = find_duplicate(fields.iter().map(|f| &f.name)) {
+            bail!("Duplicated field '{}' in struct '{}'", dup, name)
         }
-        Ok(Struct::new(new_name, vis, attrs, fields))
+        Ok(Struct {
+            name,
+            vis,
+            attrs,
+            fields,
+        })
+    }
+
+    /// Create a Struct, renaming fields which have the same name as an
+    /// earlier field. See `Disambiguation`.
+    pub fn disambiguated(
+        name: TypeId,
+        vis: Visibility,
+        attrs: Attributes,
+        mut fields: Vec<Field>,
+        strategy: &Disambiguation,
+    ) -> Result<Struct> {
+        strategy.apply(fields.iter_mut().map(|f| (&mut f.name, &mut f.attrs)))?;
+        Struct::new(name, vis, attrs, fields)
+    }
+
+    pub fn merge(
+        new_name: TypeId,
+        vis: Visibility,
+        attrs: Attributes,
+        structs: &[Struct],
+    ) -> Result<Struct> {
+        let fields = structs.iter().flat_map(|s| s.fields.iter().cloned()).collect();
+        Struct::new(new_name, vis, attrs, fields)
     }
 
 }
 
 impl fmt::Display for Struct {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let fields = render_delimited(&self.fields, ", ");
-        write!(
-            f,
-            "{} {} struct {} {{ {} }}",
-            self.attrs,
-            self.vis,
-            self.name,
-            fields
-        )
+        let mut w = Writer::new();
+        w.lines(self.attrs.to_string());
+        w.block(&format!("{}struct {}", self.vis, self.name), &self.fields);
+        write!(f, "{}", w.finish())
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, new)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Enum {
     name: Id,
     vis: Visibility,
@@ -161,21 +273,62 @@ pub struct Enum {
     variants: Vec<Variant>,
 }
 
+impl Enum {
+    /// Create an Enum, failing if two variants have the same name
+    pub fn new(
+        name: TypeId,
+        vis: Visibility,
+        attrs: Attributes,
+        variants: Vec<Variant>,
+    ) -> Result<Enum> {
+        let name = Id::from(name);
+        if let Some(dup) = find_duplicate(variants.iter().map(|v| &v.name)) {
+            bail!("Duplicated variant '{}' in enum '{}'", dup, name)
+        }
+        let mut values = BTreeSet::new();
+        let mut next = 0;
+        for variant in &variants {
+            if variant.discriminant.is_some() && variant.typ.is_some() {
+                bail!("Variant '{}' of enum '{}' has both data and a value", variant.name, name)
+            }
+            let value = variant.discriminant.unwrap_or(next);
+            if !values.insert(value) {
+                bail!("Duplicated value {} in enum '{}'", value, name)
+            }
+            next = value.wrapping_add(1);
+        }
+        Ok(Enum {
+            name,
+            vis,
+            attrs,
+            variants,
+        })
+    }
+
+    /// Create an Enum, renaming variants which have the same name as an
+    /// earlier variant. See `Disambiguation`.
+    pub fn disambiguated(
+        name: TypeId,
+        vis: Visibility,
+        attrs: Attributes,
+        mut variants: Vec<Variant>,
+        strategy: &Disambiguation,
+    ) -> Result<Enum> {
+        strategy.apply(variants.iter_mut().map(|v| (&mut v.name, &mut v.attrs)))?;
+        Enum::new(name, vis, attrs, variants)
+    }
+}
+
 impl fmt::Display for Enum {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let variants = render_delimited(&self.variants, ", ");
-        write!(
-            f,
-            "{} {} enum {} {{ {} }}",
-            self.attrs,
-            self.vis,
-            self.name,
-            variants
-        )
+        let mut w = Writer::new();
+        w.lines(self.attrs.to_string());
+        w.block(&format!("{}enum {}", self.vis, self.name), &self.variants);
+        write!(f, "{}", w.finish())
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, new)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NewType {
     name: Id,
     vis: Visibility,
@@ -183,29 +336,84 @@ pub struct NewType {
     typ: Type,
 }
 
+impl NewType {
+    pub fn new(name: TypeId, vis: Visibility, attrs: Attributes, typ: Type) -> NewType {
+        NewType {
+            name: name.into(),
+            vis,
+            attrs,
+            typ,
+        }
+    }
+}
+
 impl fmt::Display for NewType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{} {} struct {}({});",
-            self.attrs,
-            self.vis,
-            self.name,
-            self.typ
-        )
+        let mut w = Writer::new();
+        w.lines(self.attrs.to_string());
+        let header = format!("{}struct {}", self.vis, self.name);
+        w.doc(&Doc::tuple_struct(header, vec![self.typ.doc()]), ";");
+        write!(f, "{}", w.finish())
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, new)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Alias {
     name: Id,
     vis: Visibility,
     typ: Type,
 }
 
+impl Alias {
+    pub fn new(name: TypeId, vis: Visibility, typ: Type) -> Alias {
+        Alias {
+            name: name.into(),
+            vis,
+            typ,
+        }
+    }
+}
+
 impl fmt::Display for Alias {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} type {} = {};", self.vis, self.name, self.typ)
+        let mut w = Writer::new();
+        w.assign(&format!("{}type {} =", self.vis, self.name), &self.typ.doc(), ";");
+        write!(f, "{}", w.finish())
+    }
+}
+
+/// Represents a Rust `const` item. The value is a Rust expression
+/// and is rendered verbatim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Const {
+    name: Id,
+    vis: Visibility,
+    typ: Type,
+    value: String,
+}
+
+impl Const {
+    /// A const, whose name must be SCREAMING_SNAKE_CASE (see `ValueId::constant`)
+    pub fn new(name: ValueId, vis: Visibility, typ: Type, value: String) -> Const {
+        Const {
+            name: name.into(),
+            vis,
+            typ,
+            value,
+        }
+    }
+}
+
+impl fmt::Display for Const {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}const {}: {} = {};",
+            self.vis,
+            self.name,
+            self.typ,
+            self.value
+        )
     }
 }
 
@@ -213,6 +421,7 @@ impl fmt::Display for Alias {
 pub struct Attributes {
     derive: BTreeSet<Derive>,
     cfg: BTreeSet<Cfg>,
+    serde: BTreeSet<Serde>,
     custom: BTreeSet<String>,
 }
 
@@ -231,119 +440,309 @@ impl Attributes {
         self
     }
 
+    pub fn serde(mut self, serdes: &[Serde]) -> Self {
+        for s in serdes {
+            self.serde.insert(s.clone());
+        }
+        self
+    }
+
     pub fn custom(mut self, customs: &[String]) -> Self {
         for c in customs {
             self.custom.insert(c.to_string());
         }
         self
     }
+
+    /// The `rename_all` rule, if any
+    pub(crate) fn rename_all(&self) -> Option<Case> {
+        self.serde
+            .iter()
+            .filter_map(|s| match *s {
+                Serde::RenameAll(case) => Some(case),
+                _ => None,
+            })
+            .next()
+    }
 }
 
+/// Each attribute on its own line
 impl fmt::Display for Attributes {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut w = Writer::new();
         if self.derive.len() > 0 {
-            let derives = render_delimited(&self.derive.iter().collect::<Vec<_>>(), ", ");
-            write!(f, "#[derive({})]", derives)?;
+            w.attribute("derive", &render_each(&self.derive), "");
         }
         if self.cfg.len() > 0 {
-            let cfgs = render_delimited(&self.cfg.iter().collect::<Vec<_>>(), ", ");
-            write!(f, "#[cfg({})]", cfgs)?;
+            w.attribute("cfg", &render_each(&self.cfg), "");
         }
-        if self.custom.len() > 0 {
-            let customs = render_delimited(&self.custom.iter().collect::<Vec<_>>(), ", ");
-            write!(f, "#[{}]", customs)?;
+        if self.serde.len() > 0 {
+            w.attribute("serde", &render_each(&self.serde), "");
         }
-        Ok(())
+        for custom in &self.custom {
+            w.line(format!("#[{}]", custom));
+        }
+        write!(f, "{}", w.finish())
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, new)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Field {
     pub name: Id,
     pub typ: Type,
     pub attrs: Vec<FieldAttr>, // TODO separate field attrs?
+    pub vis: Visibility,
 }
 
 impl fmt::Display for Field {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let attrs = render_delimited(&self.attrs, " ");
-        write!(f, "{} {}:{}", attrs, self.name, self.typ)
+        let mut w = Writer::new();
+        self.layout(&mut w, "");
+        write!(f, "{}", w.finish())
+    }
+}
+
+impl Layout for Field {
+    fn layout(&self, w: &mut Writer, suffix: &str) {
+        for attr in &self.attrs {
+            attr.layout(w, suffix);
+        }
+        w.assign(&format!("{}{}:", self.vis, self.name), &self.typ.doc(), suffix);
     }
 }
 
 impl Field {
+    pub fn new(name: ValueId, typ: Type, attrs: Vec<FieldAttr>) -> Field {
+        Field {
+            name: name.into(),
+            typ,
+            attrs,
+            vis: Visibility::Private,
+        }
+    }
+
+    /// Make the field visible outside its struct, as in `pub name: Type`
+    pub fn vis(mut self, vis: Visibility) -> Self {
+        self.vis = vis;
+        self
+    }
+
     /// Create a Field with the poss
     pub fn with_rename<I: Into<String>>(id: I, typ: Type) -> Result<Field> {
+        Field::with_rename_escaped(id, typ, KeywordEscape::Underscore)
+    }
+
+    /// As `with_rename`, choosing how keywords are escaped.
+    /// With `KeywordEscape::Raw`, `"type"` becomes `r#type` and needs no rename.
+    pub fn with_rename_escaped<I: Into<String>>(
+        id: I,
+        typ: Type,
+        escape: KeywordEscape,
+    ) -> Result<Field> {
         let id: String = id.into();
-        if id.is_snake_case() {
-            let name = Id::make_valid(id)?;
-            Ok(Field {
-                name,
-                typ,
-                attrs: vec![],
-            })
+        let snake = if id.is_snake_case() {
+            id.clone()
         } else {
-            let name = Id::make_valid(id.to_snake_case())?;
-            let attrs = vec![FieldAttr::SerdeRename(id)];
-            Ok(Field { name, typ, attrs })
-        }
+            // Inflector keeps punctuation with a `_` before it, so once the
+            // punctuation is stripped `a-` would leave `a_`
+            let snake: String = id
+                .to_snake_case()
+                .chars()
+                .filter(|&c| utils::is_ident_continue(c))
+                .collect();
+            if id.ends_with('_') {
+                snake
+            } else {
+                snake.trim_end_matches('_').into()
+            }
+        };
+        let name = Id::make_valid_escaped(snake, escape)?;
+        let attrs = if name.unraw() == id {
+            vec![]
+        } else {
+            vec![FieldAttr::SerdeRename(id)]
+        };
+        Ok(Field {
+            name,
+            typ,
+            attrs,
+            vis: Visibility::Private,
+        })
     }
 
-    pub(crate) fn get_named_type(&self) -> Option<&Id> {
-        self.typ.get_named_root()
+    /// The name this field has once serialized by `serde`, given the
+    /// `rename_all` of its struct
+    pub(crate) fn serde_name(&self, rename_all: Option<Case>) -> Cow<'_, str> {
+        naming::serde_name(&self.name, &self.attrs, rename_all, |case, name| {
+            case.apply_to_field(name)
+        })
     }
 
-    pub(crate) fn is_defaultable(&self, map: &ItemMap) -> bool {
-        self.typ.is_defaultable(map)
+    pub(crate) fn is_serde_default(&self) -> bool {
+        self.attrs.contains(&FieldAttr::SerdeDefault)
     }
 
-    pub(crate) fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool {
-        self.typ.contains_unboxed_id(id, map)
+    pub(crate) fn derive_deps(&self, derive: &Derive) -> Option<Vec<&Type>> {
+        self.typ.derive_deps(derive)
+    }
+
+    pub(crate) fn unboxed_ids(&self) -> Vec<&Id> {
+        self.typ.unboxed_ids()
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, new)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Variant {
     name: Id,
     typ: Option<Type>,
     attrs: Vec<FieldAttr>, // TODO separate field attrs?
+    /// An explicit value, for unit variants only
+    discriminant: Option<i64>,
 }
 
 impl fmt::Display for Variant {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let attrs = render_delimited(&self.attrs, ", ");
-        match self.typ {
-            Some(ref t) => write!(f, "{} {}({})", attrs, self.name, t),
-            None => write!(f, "{} {}", attrs, self.name),
+        let mut w = Writer::new();
+        self.layout(&mut w, "");
+        write!(f, "{}", w.finish())
+    }
+}
+
+impl Layout for Variant {
+    fn layout(&self, w: &mut Writer, suffix: &str) {
+        for attr in &self.attrs {
+            attr.layout(w, suffix);
+        }
+        match (&self.typ, self.discriminant) {
+            (Some(t), _) => {
+                w.doc(&Doc::tuple_struct(self.name.to_string(), vec![t.doc()]), suffix)
+            }
+            (None, Some(value)) => w.line(format!("{} = {}{}", self.name, value, suffix)),
+            (None, None) => w.line(format!("{}{}", self.name, suffix)),
         }
     }
 }
 
 impl Variant {
-    // TODO make this into a fold
-    fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool {
-        match self.typ {
-            Some(ref typ) => typ.contains_unboxed_id(id, map),
-            None => false,
+    pub fn new(name: TypeId, typ: Option<Type>, attrs: Vec<FieldAttr>) -> Variant {
+        Variant {
+            name: name.into(),
+            typ,
+            attrs,
+            discriminant: None,
         }
+    }
 
+    /// Create a Variant with an UpperCamelCase name converted from `id`,
+    /// keeping the original as a serde rename if it differs, so
+    /// `"in-progress"` becomes `InProgress`. Names which collide after
+    /// conversion are told apart by `Enum::disambiguated`.
+    pub fn with_rename<I: AsRef<str>>(id: I, typ: Option<Type>) -> Result<Variant> {
+        NamingPolicy::default().variant(id.as_ref(), typ)
     }
-    pub(crate) fn get_named_type(&self) -> Option<&Id> {
+
+    /// Give a unit variant an explicit value, such as `Done = 2`
+    pub fn discriminant(mut self, value: i64) -> Self {
+        self.discriminant = Some(value);
+        self
+    }
+
+    pub(crate) fn unboxed_ids(&self) -> Vec<&Id> {
         match self.typ {
-            Some(ref typ) => typ.get_named_root(),
-            None => None,
+            Some(ref typ) => typ.unboxed_ids(),
+            None => vec![],
+        }
+    }
+
+    /// The name this variant has once serialized by `serde`, given the
+    /// `rename_all` of its enum
+    pub(crate) fn serde_name(&self, rename_all: Option<Case>) -> Cow<'_, str> {
+        naming::serde_name(&self.name, &self.attrs, rename_all, |case, name| {
+            case.apply_to_variant(name)
+        })
+    }
+}
+
+fn find_duplicate<'a, I: Iterator<Item = &'a Id>>(mut names: I) -> Option<&'a Id> {
+    let mut seen = BTreeSet::new();
+    names.find(|n| !seen.insert(*n))
+}
+
+/// How `Struct::disambiguated` and `Enum::disambiguated` rename fields and
+/// variants whose names collide, typically after mangling (`"foo-bar"` and
+/// `"Foo Bar"` both become `foo_bar`). The first occurrence keeps its name
+/// and later ones get the lowest free numeric suffix, e.g. `foo_bar2`.
+/// Renamed members get a `SerdeRename` to their original name, unless
+/// they already have one.
+///
+/// # Example
+///
+/// ```ignore
+/// // foo_bar, foo_bar_1, foo_bar_2, ...
+/// let strategy = Disambiguation::default().separator("_").first(1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disambiguation {
+    separator: String,
+    first: usize,
+}
+
+impl Default for Disambiguation {
+    fn default() -> Disambiguation {
+        Disambiguation {
+            separator: String::new(),
+            first: 2,
         }
+    }
+}
 
+impl Disambiguation {
+    /// Text placed between the name and the number
+    pub fn separator(mut self, separator: &str) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// The number given to the first renamed member
+    pub fn first(mut self, first: usize) -> Self {
+        self.first = first;
+        self
+    }
+
+    fn apply<'a, I>(&self, members: I) -> Result<()>
+    where
+        I: Iterator<Item = (&'a mut Id, &'a mut Vec<FieldAttr>)>,
+    {
+        let members: Vec<_> = members.collect();
+        let mut taken: BTreeSet<Id> = members.iter().map(|(name, _)| (**name).clone()).collect();
+        let mut seen = BTreeSet::new();
+        for (name, attrs) in members {
+            if seen.insert(name.clone()) {
+                continue;
+            }
+            let mut number = self.first;
+            let new = loop {
+                // a keyword with a suffix is no longer a keyword, so needs no `r#`
+                let candidate = Id::new(format!("{}{}{}", name.unraw(), self.separator, number))?;
+                if !taken.contains(&candidate) {
+                    break candidate;
+                }
+                number += 1;
+            };
+            let has_rename = attrs.iter().any(|attr| matches!(*attr, FieldAttr::SerdeRename(_)));
+            if !has_rename {
+                attrs.push(FieldAttr::SerdeRename(name.unraw().to_string()));
+            }
+            taken.insert(new.clone());
+            seen.insert(new.clone());
+            *name = new;
+        }
+        Ok(())
     }
 }
 
-fn render_delimited<T: fmt::Display>(items: &[T], delimiter: &str) -> String {
-    items
-        .iter()
-        .map(|item| format!("{}", item))
-        .collect::<Vec<String>>()
-        .join(delimiter)
-        .to_string()
+fn render_each<'a, T: fmt::Display + 'a, I: IntoIterator<Item = &'a T>>(items: I) -> Vec<String> {
+    items.into_iter().map(|item| item.to_string()).collect()
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -378,11 +777,22 @@ pub enum FieldAttr {
 
 impl fmt::Display for FieldAttr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut w = Writer::new();
+        self.layout(&mut w, "");
+        write!(f, "{}", w.finish())
+    }
+}
+
+impl Layout for FieldAttr {
+    /// `suffix` is that of the field or variant, which is written after it
+    fn layout(&self, w: &mut Writer, suffix: &str) {
         use FieldAttr::*;
         match *self {
-            SerdeDefault => write!(f, "#[serde(default)]"),
-            SerdeRename(ref name) => write!(f, "#[serde(rename = \"{}\")]", name),
-            Custom(ref name) => write!(f, "{}", name),
+            SerdeDefault => w.line("#[serde(default)]"),
+            SerdeRename(ref name) => {
+                w.attribute("serde", &[format!("rename = {:?}", name)], suffix)
+            }
+            Custom(ref name) => w.lines(name),
         }
     }
 }
@@ -406,9 +816,30 @@ pub enum Derive {
     Hash,
     PartialOrd,
     Ord,
+    Default,
     Custom(String),
 }
 
+impl Derive {
+    /// The derives which can be inferred by `ItemMap::apply_derives`
+    pub fn standard() -> Vec<Derive> {
+        use Derive::*;
+        vec![Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default]
+    }
+
+    /// Other derives which must be present for this one to compile
+    pub(crate) fn requires(&self) -> Vec<Derive> {
+        use Derive::*;
+        match *self {
+            Copy => vec![Clone],
+            Eq => vec![PartialEq],
+            PartialOrd => vec![PartialEq],
+            Ord => vec![PartialEq, Eq, PartialOrd],
+            _ => vec![],
+        }
+    }
+}
+
 impl fmt::Display for Derive {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -430,7 +861,33 @@ impl fmt::Display for Cfg {
         use Cfg::*;
         match *self {
             Test => write!(f, "test"),
-            TargetOs(ref target) => write!(f, "target_os = \"{}\"", target),
+            TargetOs(ref target) => write!(f, "target_os = {:?}", target),
+            Custom(ref custom) => write!(f, "{}", custom),
+        }
+    }
+}
+
+/// Container-level `serde` attributes
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Serde {
+    /// Internally tagged enum representation
+    Tag(String),
+    /// Adjacently tagged enum representation, used together with `Tag`
+    Content(String),
+    Untagged,
+    /// Rename every field or variant which has no `SerdeRename`
+    RenameAll(Case),
+    Custom(String),
+}
+
+impl fmt::Display for Serde {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Serde::*;
+        match *self {
+            Tag(ref tag) => write!(f, "tag = {:?}", tag),
+            Content(ref content) => write!(f, "content = {:?}", content),
+            Untagged => write!(f, "untagged"),
+            RenameAll(case) => write!(f, "rename_all = \"{}\"", case.serde_name()),
             Custom(ref custom) => write!(f, "{}", custom),
         }
     }
@@ -439,15 +896,15 @@ impl fmt::Display for Cfg {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use Derive::*;
+    use Derive::{Clone, Debug, Eq};
     use Cfg::*;
     use FieldAttr::*;
-    use utils::rust_format;
+    use utils::rendered;
 
     #[test]
     fn test_struct() {
         let my_struct = Struct::new(
-            Id::new("MyStruct").unwrap(),
+            TypeId::new("MyStruct").unwrap(),
             Visibility::Public,
             Attributes::default().derive(&[Clone, Debug]).cfg(
                 &[
@@ -459,21 +916,21 @@ mod tests {
             ),
             vec![
                 Field::new(
-                    Id::new("field1").unwrap(),
+                    ValueId::new("field1").unwrap(),
                     Type::named("Type1").unwrap(),
                     Default::default()
                 ),
                 Field::new(
-                    Id::new("field2").unwrap(),
+                    ValueId::new("field2").unwrap(),
                     Type::Box(Box::new(Type::named("Type2").unwrap())),
                     vec![SerdeRename("Field-2".into()), SerdeDefault]
                 ),
                 Field::with_rename("Snake Case Me", Type::named("Type3").unwrap())
                     .unwrap(),
             ],
-        );
+        ).unwrap();
 
-        let pretty = rust_format(&my_struct.to_string()).unwrap();
+        let pretty = rendered(&my_struct);
         let expect = r#"#[derive(Debug, Clone)]
 #[cfg(test, target_os = "linux")]
 pub struct MyStruct {
@@ -491,26 +948,28 @@ pub struct MyStruct {
     #[test]
     fn test_enum() {
         let e = Enum::new(
-            Id::new("MyEnum").unwrap(),
+            TypeId::new("MyEnum").unwrap(),
             Visibility::Crate,
             Attributes::default()
                 .derive(&[Clone, Eq, Derive::Custom("MyDerive".into())])
+                .serde(&[Serde::Tag("kind".into())])
                 .custom(&["my_custom_attribute".into()]),
             vec![
                 Variant::new(
-                    Id::new("Variant1").unwrap(),
+                    TypeId::new("Variant1").unwrap(),
                     Default::default(),
                     vec![FieldAttr::SerdeRename("used-to-be-this".into())]
                 ),
                 Variant::new(
-                    Id::new("Variant2").unwrap(),
+                    TypeId::new("Variant2").unwrap(),
                     Some(Type::named("VType").unwrap()),
                     Default::default()
                 ),
             ],
-        );
-        let pretty = rust_format(&e.to_string()).unwrap();
+        ).unwrap();
+        let pretty = rendered(&e);
         let expect = r#"#[derive(Clone, Eq, MyDerive)]
+#[serde(tag = "kind")]
 #[my_custom_attribute]
 pub(crate) enum MyEnum {
     #[serde(rename = "used-to-be-this")]
@@ -524,12 +983,12 @@ pub(crate) enum MyEnum {
     #[test]
     fn test_newtype() {
         let n = NewType::new(
-            Id::new("MyNewType").unwrap(),
+            TypeId::new("MyNewType").unwrap(),
             Visibility::Private,
             Default::default(),
             Type::named("MyOldType").unwrap(),
         );
-        let pretty = rust_format(&n.to_string()).unwrap();
+        let pretty = rendered(&n);
         let expect = "struct MyNewType(MyOldType);\n";
         assert_eq!(pretty, expect);
     }
@@ -537,12 +996,265 @@ pub(crate) enum MyEnum {
     #[test]
     fn test_alias() {
         let a = Alias::new(
-            Id::new("MyAlias").unwrap(),
+            TypeId::new("MyAlias").unwrap(),
             Visibility::Crate,
             Type::named("MyAliasedType").unwrap(),
         );
-        let pretty = rust_format(&a.to_string()).unwrap();
+        let pretty = rendered(&a);
         let expect = "pub(crate) type MyAlias = MyAliasedType;\n";
         assert_eq!(pretty, expect);
     }
+
+    #[test]
+    fn test_long_lines() {
+        let mut derives = Derive::standard();
+        derives.push(Derive::Custom("Serialize".into()));
+        derives.push(Derive::Custom("Deserialize".into()));
+        let named = |name: &str| Type::named(name).unwrap();
+        let map = Type::Map(
+            Box::new(Type::Primitive(Primitive::String)),
+            Box::new(Type::Vec(Box::new(named("AVeryLongTypeNameForTheValues")))),
+        );
+        let s = Struct::new(
+            TypeId::new("LongLines").unwrap(),
+            Visibility::Public,
+            Attributes::default().derive(&derives),
+            vec![
+                Field::new(
+                    ValueId::new("a_field_with_a_long_name").unwrap(),
+                    Type::Result(Box::new(map), Box::new(named("AnotherLongErrorTypeName"))),
+                    vec![SerdeRename(format!("{}by-the-api", "as-spelled-on-the-wire-".repeat(3)))],
+                ),
+            ],
+        ).unwrap();
+        let pretty = rendered(&s);
+        let expect = r#"#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Deserialize, Serialize,
+)]
+pub struct LongLines {
+    #[serde(
+        rename = "as-spelled-on-the-wire-as-spelled-on-the-wire-as-spelled-on-the-wire-by-the-api"
+    )]
+    a_field_with_a_long_name:
+        Result<Map<String, Vec<AVeryLongTypeNameForTheValues>>, AnotherLongErrorTypeName>,
+}
+"#;
+        assert_eq!(pretty, expect);
+    }
+
+    #[test]
+    fn test_const() {
+        let c = Const::new(
+            ValueId::constant("GREETING").unwrap(),
+            Visibility::Public,
+            Type::Ref(None, Box::new(Type::Primitive(Primitive::Str))),
+            "\"hello\"".into(),
+        );
+        let pretty = rendered(&c);
+        let expect = "pub const GREETING: &str = \"hello\";\n";
+        assert_eq!(pretty, expect);
+    }
+
+    #[test]
+    fn test_disambiguation() {
+        let typ = || Type::Primitive(Primitive::I32);
+        let fields = vec![
+            Field::with_rename("foo-bar", typ()).unwrap(),
+            Field::with_rename("foo_bar", typ()).unwrap(),
+            Field::with_rename("Foo Bar", typ()).unwrap(),
+            Field::new(ValueId::new("foo_bar2").unwrap(), typ(), vec![]),
+        ];
+        let id = |name: &str| TypeId::new(name).unwrap();
+        let err = Struct::new(id("S"), Visibility::Public, Attributes::default(), fields.clone())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Duplicated field 'foo_bar' in struct 'S'");
+
+        let s = Struct::disambiguated(
+            id("S"),
+            Visibility::Public,
+            Attributes::default(),
+            fields,
+            &Disambiguation::default(),
+        ).unwrap();
+        let names: Vec<(&str, String)> = s.fields
+            .iter()
+            .map(|f| (&*f.name, f.serde_name(None).into_owned()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("foo_bar", "foo-bar".into()),
+                ("foo_bar3", "foo_bar".into()),
+                ("foo_bar4", "Foo Bar".into()),
+                ("foo_bar2", "foo_bar2".into()),
+            ]
+        );
+
+        let variants = vec![
+            Variant::new(id("FooBar"), None, vec![]),
+            Variant::new(id("FooBar"), None, vec![SerdeRename("foo-bar".into())]),
+        ];
+        assert!(Enum::new(id("E"), Visibility::Public, Attributes::default(), variants.clone())
+            .is_err());
+        let e = Enum::disambiguated(
+            id("E"),
+            Visibility::Public,
+            Attributes::default(),
+            variants,
+            &Disambiguation::default().separator("_").first(1),
+        ).unwrap();
+        let names: Vec<(&str, String)> = e.variants
+            .iter()
+            .map(|v| (&*v.name, v.serde_name(None).into_owned()))
+            .collect();
+        assert_eq!(names, vec![("FooBar", "FooBar".into()), ("FooBar_1", "foo-bar".into())]);
+    }
+
+    #[test]
+    fn test_variant_with_rename() {
+        let variant = |name| Variant::with_rename(name, None).unwrap();
+        assert_eq!(variant("in-progress").name, Id::new("InProgress").unwrap());
+        assert_eq!(variant("in-progress").attrs, vec![SerdeRename("in-progress".into())]);
+        assert_eq!(variant("v1.0").name, Id::new("V10").unwrap());
+        assert_eq!(variant("Done").attrs, vec![]);
+        assert_eq!(variant("self").name, Id::new("Self_").unwrap());
+        assert!(Variant::with_rename("???", None).is_err());
+
+        let variants = vec![variant("v1.0"), variant("v10"), variant("V10")];
+        let e = Enum::disambiguated(
+            TypeId::new("Version").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            variants,
+            &Disambiguation::default(),
+        ).unwrap();
+        let names: Vec<(&str, String)> = e.variants
+            .iter()
+            .map(|v| (&*v.name, v.serde_name(None).into_owned()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("V10", "v1.0".into()), ("V102", "v10".into()), ("V103", "V10".into())]
+        );
+    }
+
+    #[test]
+    fn test_escaped_strings() {
+        let typ = || Type::Primitive(Primitive::String);
+        let s = Struct::new(
+            TypeId::new("Quoted").unwrap(),
+            Visibility::Public,
+            Attributes::default().serde(&[Serde::Tag("a\"b".into())]),
+            vec![
+                Field::with_rename("say \"hi\"", typ()).unwrap(),
+                Field::with_rename("back\\slash", typ()).unwrap(),
+            ],
+        ).unwrap();
+        let expect = r#"#[serde(tag = "a\"b")]
+pub struct Quoted {
+    #[serde(rename = "say \"hi\"")]
+    say_hi: String,
+    #[serde(rename = "back\\slash")]
+    back_slash: String,
+}"#;
+        assert_eq!(s.to_string(), expect);
+        assert!(check_syntax(&s.to_string()).is_ok());
+        // the original names are kept unescaped
+        assert_eq!(s.fields[0].serde_name(None), "say \"hi\"");
+        assert_eq!(s.fields[1].serde_name(None), "back\\slash");
+        assert_eq!(&*Field::with_rename("a-", typ()).unwrap().name, "a");
+        assert_eq!(&*Field::with_rename("a_", typ()).unwrap().name, "a_");
+    }
+
+    #[test]
+    fn test_raw_identifiers() {
+        let typ = || Type::Primitive(Primitive::String);
+        let raw = Field::with_rename_escaped("type", typ(), KeywordEscape::Raw).unwrap();
+        assert_eq!(&*raw.name, "r#type");
+        assert_eq!(raw.name.unraw(), "type");
+        assert!(raw.attrs.is_empty());
+        assert_eq!(raw.serde_name(None), "type");
+
+        let mangled = Field::with_rename("type", typ()).unwrap();
+        assert_eq!(&*mangled.name, "type_");
+        assert_eq!(mangled.serde_name(None), "type");
+
+        let not_raw = Field::with_rename_escaped("self", typ(), KeywordEscape::Raw).unwrap();
+        assert_eq!(&*not_raw.name, "self_");
+        assert_eq!(not_raw.serde_name(None), "self");
+
+        let s = Struct::new(
+            TypeId::new("Token").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![raw, not_raw],
+        ).unwrap();
+        let pretty = rendered(&s);
+        let expect = r#"pub struct Token {
+    r#type: String,
+    #[serde(rename = "self")]
+    self_: String,
+}
+"#;
+        assert_eq!(pretty, expect);
+
+        // a second raw field is renamed to its unescaped spelling
+        let raw = || Field::with_rename_escaped("type", typ(), KeywordEscape::Raw).unwrap();
+        let s = Struct::disambiguated(
+            TypeId::new("Token").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![raw(), raw()],
+            &Disambiguation::default(),
+        ).unwrap();
+        let expect = r#"pub struct Token {
+    r#type: String,
+    #[serde(rename = "type")]
+    type2: String,
+}"#;
+        assert_eq!(s.to_string(), expect);
+    }
+
+    #[test]
+    fn test_validate_syntax() {
+        let typ = || Type::Primitive(Primitive::U32);
+        let good = Struct::new(
+            TypeId::new("Good").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![Field::new(ValueId::new("x").unwrap(), typ(), vec![SerdeRename("y\"z".into())])],
+        ).unwrap();
+        let bad = Struct::new(
+            TypeId::new("Bad").unwrap(),
+            Visibility::Public,
+            Attributes::default(),
+            vec![
+                Field::new(ValueId::new("x").unwrap(), typ(), Default::default()),
+                Field::new(
+                    ValueId::new("y").unwrap(),
+                    typ(),
+                    vec![FieldAttr::Custom("#[serde(rename = 1)]".into())],
+                ),
+            ],
+        ).unwrap();
+        let bogus = Struct::new(
+            TypeId::new("Bogus").unwrap(),
+            Visibility::Public,
+            Attributes::default().serde(&[Serde::Custom("rename_all = bogus".into())]),
+            vec![Field::new(ValueId::new("x").unwrap(), typ(), Default::default())],
+        ).unwrap();
+        assert!(good.syntax_error().is_none());
+        assert!(bogus.syntax_error().is_some());
+        let map = ItemMap::build(vec![Box::new(good), Box::new(bad)]).unwrap();
+        let errors = map.syntax_errors();
+        assert_eq!(errors.len(), 1);
+        let error = &errors[0];
+        assert_eq!(error.item, Some(Id::new("Bad").unwrap()));
+        assert_eq!((error.line, error.column), (3, 22));
+        assert_eq!(error.snippet, "    #[serde(rename = 1)]");
+        match *map.validate_syntax().unwrap_err().kind() {
+            ErrorKind::Syntax(ref message) => assert!(message.starts_with("in 'Bad' at line 3")),
+            ref other => panic!("Expected a syntax error, got {}", other),
+        }
+    }
 }
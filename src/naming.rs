@@ -0,0 +1,510 @@
+//! Converting names from a schema into idiomatic Rust names
+//!
+//! `NamingPolicy` turns types and variants into UpperCamelCase, fields
+//! into snake_case and consts into SCREAMING_SNAKE_CASE. Where the Rust
+//! name differs from the original, the original is kept for `serde`,
+//! either with a `rename` on the member or a `rename_all` on the container.
+
+use std::borrow::Cow;
+
+use errors::*;
+use {Attributes, Disambiguation, Edition, Enum, Field, FieldAttr, Id, KeywordEscape, Serde,
+     Struct, Type, TypeId, ValueId, Variant, Visibility};
+
+/// A `serde` `rename_all` rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Case {
+    /// `lowercase`
+    Lower,
+    /// `UPPERCASE`
+    Upper,
+    /// `PascalCase`
+    UpperCamel,
+    /// `camelCase`
+    Camel,
+    /// `snake_case`
+    Snake,
+    /// `SCREAMING_SNAKE_CASE`
+    ScreamingSnake,
+    /// `kebab-case`
+    Kebab,
+    /// `SCREAMING-KEBAB-CASE`
+    ScreamingKebab,
+}
+
+use self::Case::*;
+
+/// The order in which rules are tried when looking for a `rename_all`
+const CASES: &[Case] = &[
+    Camel,
+    UpperCamel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+    Lower,
+    Upper,
+];
+
+impl Case {
+    /// The name `serde` gives the rule
+    pub fn serde_name(&self) -> &'static str {
+        match *self {
+            Lower => "lowercase",
+            Upper => "UPPERCASE",
+            UpperCamel => "PascalCase",
+            Camel => "camelCase",
+            Snake => "snake_case",
+            ScreamingSnake => "SCREAMING_SNAKE_CASE",
+            Kebab => "kebab-case",
+            ScreamingKebab => "SCREAMING-KEBAB-CASE",
+        }
+    }
+
+    pub fn from_serde_name(name: &str) -> Option<Case> {
+        CASES.iter().cloned().find(|case| case.serde_name() == name)
+    }
+
+    /// Rename a snake_case field the way `serde` does
+    pub fn apply_to_field(&self, field: &str) -> String {
+        match *self {
+            Lower | Snake => field.into(),
+            Upper | ScreamingSnake => field.to_ascii_uppercase(),
+            UpperCamel => {
+                let mut out = String::new();
+                let mut capitalize = true;
+                for c in field.chars() {
+                    if c == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        out.extend(c.to_uppercase());
+                        capitalize = false;
+                    } else {
+                        out.push(c);
+                    }
+                }
+                out
+            }
+            Camel => {
+                let pascal = UpperCamel.apply_to_field(field);
+                lower_first(&pascal)
+            }
+            Kebab => field.replace('_', "-"),
+            ScreamingKebab => ScreamingSnake.apply_to_field(field).replace('_', "-"),
+        }
+    }
+
+    /// Rename an UpperCamelCase variant the way `serde` does
+    pub fn apply_to_variant(&self, variant: &str) -> String {
+        match *self {
+            UpperCamel => variant.into(),
+            Lower => variant.to_ascii_lowercase(),
+            Upper => variant.to_ascii_uppercase(),
+            Camel => lower_first(variant),
+            Snake => {
+                let mut out = String::new();
+                for (ix, c) in variant.chars().enumerate() {
+                    if ix > 0 && c.is_uppercase() {
+                        out.push('_');
+                    }
+                    out.extend(c.to_lowercase());
+                }
+                out
+            }
+            ScreamingSnake => Snake.apply_to_variant(variant).to_ascii_uppercase(),
+            Kebab => Snake.apply_to_variant(variant).replace('_', "-"),
+            ScreamingKebab => ScreamingSnake.apply_to_variant(variant).replace('_', "-"),
+        }
+    }
+}
+
+fn lower_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Split a name into words at punctuation, at lower-to-upper case changes
+/// and at the end of acronyms, so `HTTPServer_v2` becomes
+/// `["HTTP", "Server", "v2"]`. Digits stay with the word before them.
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (ix, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(word);
+                word = String::new();
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let prev = chars[ix - 1];
+            let next_is_lower = chars.get(ix + 1).is_some_and(|n| n.is_lowercase());
+            if !prev.is_uppercase() || next_is_lower {
+                words.push(word);
+                word = String::new();
+            }
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+fn upper_camel(name: &str) -> String {
+    words(name).iter().map(|w| capitalize(w)).collect()
+}
+
+fn snake(name: &str) -> String {
+    words(name).iter().map(|w| w.to_lowercase()).collect::<Vec<_>>().join("_")
+}
+
+fn screaming_snake(name: &str) -> String {
+    words(name).iter().map(|w| w.to_uppercase()).collect::<Vec<_>>().join("_")
+}
+
+/// Converts names taken from a schema into Rust naming conventions.
+/// Acronyms are treated as words, so `HTTPServer` becomes `HttpServer`
+/// and `http_server`.
+///
+/// # Example
+///
+/// ```ignore
+/// let policy = NamingPolicy::default().escape(KeywordEscape::Raw);
+/// let item = policy.strukt(
+///     "user-profile",
+///     Visibility::Public,
+///     Attributes::default(),
+///     vec![("userId".into(), Type::Primitive(Primitive::U32))],
+/// )?;
+/// // #[serde(rename_all = "camelCase")] pub struct UserProfile { user_id: u32 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamingPolicy {
+    escape: KeywordEscape,
+    edition: Edition,
+    rename_all: bool,
+    disambiguation: Disambiguation,
+}
+
+impl Default for NamingPolicy {
+    fn default() -> NamingPolicy {
+        NamingPolicy {
+            escape: KeywordEscape::Underscore,
+            edition: Edition::default(),
+            rename_all: true,
+            disambiguation: Disambiguation::default(),
+        }
+    }
+}
+
+impl NamingPolicy {
+    /// How converted names which are keywords are escaped
+    pub fn escape(mut self, escape: KeywordEscape) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// The edition whose keywords must be escaped
+    pub fn edition(mut self, edition: Edition) -> Self {
+        self.edition = edition;
+        self
+    }
+
+    /// Whether `strukt` and `enumeration` may use a container `rename_all`
+    /// instead of renaming every member. Defaults to true.
+    pub fn rename_all(mut self, rename_all: bool) -> Self {
+        self.rename_all = rename_all;
+        self
+    }
+
+    /// How members which convert to the same name are told apart
+    pub fn disambiguation(mut self, disambiguation: Disambiguation) -> Self {
+        self.disambiguation = disambiguation;
+        self
+    }
+
+    /// An UpperCamelCase name for a struct, enum, newtype or alias
+    pub fn type_name(&self, name: &str) -> Result<TypeId> {
+        TypeId::from_id(self.make_valid(upper_camel(name), name)?)
+    }
+
+    /// A SCREAMING_SNAKE_CASE name for a const
+    pub fn const_name(&self, name: &str) -> Result<ValueId> {
+        ValueId::constant_from_id(self.make_valid(screaming_snake(name), name)?)
+    }
+
+    /// A snake_case field, renamed for `serde` if the name changed
+    pub fn field(&self, name: &str, typ: Type) -> Result<Field> {
+        let id = ValueId::from_id(self.make_valid(snake(name), name)?)?;
+        let attrs = renamed(&id, name);
+        Ok(Field::new(id, typ, attrs))
+    }
+
+    /// An UpperCamelCase variant, renamed for `serde` if the name changed
+    pub fn variant(&self, name: &str, typ: Option<Type>) -> Result<Variant> {
+        let id = TypeId::from_id(self.make_valid(upper_camel(name), name)?)?;
+        let attrs = renamed(&id, name);
+        Ok(Variant::new(id, typ, attrs))
+    }
+
+    /// A struct with converted names for itself and its fields
+    pub fn strukt(
+        &self,
+        name: &str,
+        vis: Visibility,
+        attrs: Attributes,
+        fields: Vec<(String, Type)>,
+    ) -> Result<Struct> {
+        let mut converted = Vec::new();
+        for (field, typ) in fields {
+            converted.push((self.field(&field, typ)?, field));
+        }
+        let attrs = self.choose_rename_all(attrs, &mut converted, |case, id| {
+            case.apply_to_field(id)
+        });
+        let fields = converted.into_iter().map(|(field, _)| field).collect();
+        Struct::disambiguated(self.type_name(name)?, vis, attrs, fields, &self.disambiguation)
+    }
+
+    /// An enum with converted names for itself and its variants
+    pub fn enumeration(
+        &self,
+        name: &str,
+        vis: Visibility,
+        attrs: Attributes,
+        variants: Vec<(String, Option<Type>)>,
+    ) -> Result<Enum> {
+        let mut converted = Vec::new();
+        for (variant, typ) in variants {
+            converted.push((self.variant(&variant, typ)?, variant));
+        }
+        let attrs = self.choose_rename_all(attrs, &mut converted, |case, id| {
+            case.apply_to_variant(id)
+        });
+        let variants = converted.into_iter().map(|(variant, _)| variant).collect();
+        Enum::disambiguated(self.type_name(name)?, vis, attrs, variants, &self.disambiguation)
+    }
+
+    fn make_valid(&self, converted: String, original: &str) -> Result<Id> {
+        Id::make_valid_for_edition(converted, self.escape, self.edition)
+            .chain_err(|| format!("could not convert '{}' into a Rust name", original))
+    }
+
+    /// If one `serde` rule maps every member back to its original name,
+    /// add it to the container and drop the renames from the members
+    fn choose_rename_all<M, F>(
+        &self,
+        attrs: Attributes,
+        members: &mut [(M, String)],
+        apply: F,
+    ) -> Attributes
+    where
+        M: Member,
+        F: Fn(Case, &str) -> String,
+    {
+        let renamed = members.iter().any(|(m, _)| !m.attrs().is_empty());
+        if !self.rename_all || !renamed || attrs.rename_all().is_some() {
+            return attrs;
+        }
+        let case = CASES.iter().cloned().find(|&case| {
+            members
+                .iter()
+                .all(|(m, original)| apply(case, m.name().unraw()) == *original)
+        });
+        match case {
+            Some(case) => {
+                for &mut (ref mut m, _) in members.iter_mut() {
+                    m.attrs_mut().clear();
+                }
+                attrs.serde(&[Serde::RenameAll(case)])
+            }
+            None => attrs,
+        }
+    }
+}
+
+fn renamed(id: &Id, original: &str) -> Vec<FieldAttr> {
+    if id.unraw() == original {
+        vec![]
+    } else {
+        vec![FieldAttr::SerdeRename(original.into())]
+    }
+}
+
+/// Fields and variants, as far as renaming is concerned
+trait Member {
+    fn name(&self) -> &Id;
+    fn attrs(&self) -> &Vec<FieldAttr>;
+    fn attrs_mut(&mut self) -> &mut Vec<FieldAttr>;
+}
+
+impl Member for Field {
+    fn name(&self) -> &Id {
+        &self.name
+    }
+    fn attrs(&self) -> &Vec<FieldAttr> {
+        &self.attrs
+    }
+    fn attrs_mut(&mut self) -> &mut Vec<FieldAttr> {
+        &mut self.attrs
+    }
+}
+
+impl Member for Variant {
+    fn name(&self) -> &Id {
+        &self.name
+    }
+    fn attrs(&self) -> &Vec<FieldAttr> {
+        &self.attrs
+    }
+    fn attrs_mut(&mut self) -> &mut Vec<FieldAttr> {
+        &mut self.attrs
+    }
+}
+
+/// The name a member has once serialized, given its container's `rename_all`
+pub(crate) fn serde_name<'a, F>(
+    name: &'a Id,
+    attrs: &'a [FieldAttr],
+    rename_all: Option<Case>,
+    apply: F,
+) -> Cow<'a, str>
+where
+    F: Fn(Case, &str) -> String,
+{
+    let rename = attrs
+        .iter()
+        .filter_map(|attr| match *attr {
+            FieldAttr::SerdeRename(ref rename) => Some(&**rename),
+            _ => None,
+        })
+        .next_back();
+    match (rename, rename_all) {
+        (Some(rename), _) => Cow::Borrowed(rename),
+        (None, Some(case)) => Cow::Owned(apply(case, name.unraw())),
+        (None, None) => Cow::Borrowed(name.unraw()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Primitive;
+
+    #[test]
+    fn test_words() {
+        assert_eq!(words("HTTPServer_v2"), vec!["HTTP", "Server", "v2"]);
+        assert_eq!(words("XMLHttpRequest"), vec!["XML", "Http", "Request"]);
+        assert_eq!(words("user-id"), vec!["user", "id"]);
+        assert_eq!(words("userID"), vec!["user", "ID"]);
+        assert_eq!(words("  "), Vec::<String>::new());
+
+        let policy = NamingPolicy::default();
+        assert_eq!(&**policy.type_name("HTTPServer").unwrap(), "HttpServer");
+        assert_eq!(&**policy.type_name("user_profile").unwrap(), "UserProfile");
+        assert_eq!(&**policy.const_name("maxRetryCount").unwrap(), "MAX_RETRY_COUNT");
+        let field = |name| policy.field(name, Type::Primitive(Primitive::U8)).unwrap();
+        assert_eq!(&*field("HTTPServer").name, "http_server");
+        assert_eq!(&*field("type").name, "type_");
+        assert_eq!(field("type").attrs, vec![FieldAttr::SerdeRename("type".into())]);
+        assert!(policy.type_name("--").is_err());
+    }
+
+    #[test]
+    fn test_serde_cases() {
+        for &case in CASES {
+            assert_eq!(Case::from_serde_name(case.serde_name()), Some(case));
+        }
+        assert_eq!(Camel.apply_to_field("user_id"), "userId");
+        assert_eq!(UpperCamel.apply_to_field("user_id"), "UserId");
+        assert_eq!(ScreamingKebab.apply_to_field("user_id"), "USER-ID");
+        assert_eq!(Snake.apply_to_variant("InProgress"), "in_progress");
+        assert_eq!(Camel.apply_to_variant("InProgress"), "inProgress");
+        assert_eq!(Kebab.apply_to_variant("InProgress"), "in-progress");
+        assert_eq!(Upper.apply_to_variant("InProgress"), "INPROGRESS");
+    }
+
+    #[test]
+    fn test_naming_policy() {
+        let policy = NamingPolicy::default();
+        let int = || Type::Primitive(Primitive::I64);
+
+        let s = policy
+            .strukt(
+                "user-profile",
+                Visibility::Public,
+                Attributes::default(),
+                vec![
+                    ("userId".into(), int()),
+                    ("displayName".into(), int()),
+                    ("age".into(), int()),
+                ],
+            )
+            .unwrap();
+        assert_eq!(s.name, Id::new("UserProfile").unwrap());
+        assert_eq!(s.attrs.rename_all(), Some(Camel));
+        assert!(s.fields.iter().all(|f| f.attrs.is_empty()));
+        let names: Vec<_> = s.fields.iter().map(|f| f.serde_name(Some(Camel))).collect();
+        assert_eq!(names, vec!["userId", "displayName", "age"]);
+
+        // no single rule fits, so each field is renamed
+        let s = policy
+            .strukt(
+                "Mixed",
+                Visibility::Public,
+                Attributes::default(),
+                vec![("userId".into(), int()), ("display-name".into(), int())],
+            )
+            .unwrap();
+        assert_eq!(s.attrs.rename_all(), None);
+        assert_eq!(s.fields[0].attrs, vec![FieldAttr::SerdeRename("userId".into())]);
+        assert_eq!(s.fields[1].attrs, vec![FieldAttr::SerdeRename("display-name".into())]);
+
+        let e = policy
+            .enumeration(
+                "status",
+                Visibility::Public,
+                Attributes::default(),
+                vec![("IN_PROGRESS".into(), None), ("DONE".into(), Some(int()))],
+            )
+            .unwrap();
+        assert_eq!(e.name, Id::new("Status").unwrap());
+        assert_eq!(e.attrs.rename_all(), Some(ScreamingSnake));
+        assert_eq!(e.variants[0].name, Id::new("InProgress").unwrap());
+        assert_eq!(
+            e.to_string(),
+            "#[serde(rename_all = \"SCREAMING_SNAKE_CASE\")]
+pub enum Status {
+    InProgress,
+    Done(i64),
+}"
+        );
+
+        let e = NamingPolicy::default()
+            .rename_all(false)
+            .enumeration(
+                "Status",
+                Visibility::Public,
+                Attributes::default(),
+                vec![("in-progress".into(), None), ("done".into(), None)],
+            )
+            .unwrap();
+        assert_eq!(e.attrs.rename_all(), None);
+        assert_eq!(e.variants[1].serde_name(None), "done");
+    }
+}
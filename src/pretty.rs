@@ -0,0 +1,432 @@
+//! An indentation-aware writer, used by the `Display` impls of items to
+//! lay them out as `rustfmt` would with its default settings. Lines which
+//! would pass 100 columns are broken where rustfmt breaks them: attribute
+//! lists, the types of fields, variants and newtypes, and the right hand
+//! side of aliases. Text which cannot be broken, such as a long custom
+//! attribute, is left as it is.
+
+use std::fmt;
+
+const INDENT: &str = "    ";
+/// rustfmt's default `max_width`
+const MAX_WIDTH: usize = 100;
+/// rustfmt's default `fn_call_width`, the most that the items of a tuple
+/// or tuple struct may take up on one line
+const FN_CALL_WIDTH: usize = 60;
+/// rustfmt's default `attr_fn_like_width`, as `FN_CALL_WIDTH` for the
+/// items of an attribute other than `derive`
+const ATTR_WIDTH: usize = 70;
+
+/// A fragment of code which may be broken over several lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Doc {
+    /// Text which is never broken
+    Text(String),
+    /// `head` followed by bracketed `items`, such as `Result<T, E>` or
+    /// `Variant(T)`. A broken list has one item per line, except that a
+    /// list with `overflow` set and a single tuple item opens the tuple on
+    /// its own first line, as in `Vec<(`. Several items are only kept on
+    /// one line if they take up at most `limit` columns.
+    List {
+        head: String,
+        open: &'static str,
+        items: Vec<Doc>,
+        close: &'static str,
+        overflow: bool,
+        limit: Option<usize>,
+    },
+}
+
+impl Doc {
+    /// Generic arguments, as in `Result<T, E>`
+    pub(crate) fn generic<S: Into<String>>(head: S, args: Vec<Doc>) -> Doc {
+        Doc::List {
+            head: head.into(),
+            open: "<",
+            items: args,
+            close: ">",
+            overflow: true,
+            limit: None,
+        }
+    }
+
+    /// A tuple type, as in `(A, B)`
+    pub(crate) fn tuple(items: Vec<Doc>) -> Doc {
+        Doc::List {
+            head: String::new(),
+            open: "(",
+            items,
+            close: ")",
+            overflow: false,
+            limit: Some(FN_CALL_WIDTH),
+        }
+    }
+
+    /// The fields of a tuple variant or newtype, as in `Variant(T)`
+    pub(crate) fn tuple_struct<S: Into<String>>(head: S, fields: Vec<Doc>) -> Doc {
+        Doc::List {
+            head: head.into(),
+            open: "(",
+            items: fields,
+            close: ")",
+            overflow: false,
+            limit: Some(FN_CALL_WIDTH),
+        }
+    }
+
+    /// Put `prefix` in front, as the `&'a ` of a reference
+    pub(crate) fn prefixed(self, prefix: &str) -> Doc {
+        match self {
+            Doc::Text(text) => Doc::Text(format!("{}{}", prefix, text)),
+            Doc::List {
+                head,
+                open,
+                items,
+                close,
+                overflow,
+                limit,
+            } => Doc::List {
+                head: format!("{}{}", prefix, head),
+                open,
+                items,
+                close,
+                overflow,
+                limit,
+            },
+        }
+    }
+
+    /// A tuple type, possibly behind a reference
+    fn is_tuple(&self) -> bool {
+        match *self {
+            Doc::List { ref head, open, .. } => {
+                open == "(" && (head.is_empty() || head.starts_with('&'))
+            }
+            Doc::Text(_) => false,
+        }
+    }
+
+    /// Whether every list within stays within its `limit`, so that
+    /// rustfmt would keep the fragment on one line if there is room
+    fn is_short(&self) -> bool {
+        match *self {
+            Doc::Text(_) => true,
+            Doc::List { ref items, limit, .. } => {
+                let within = match limit {
+                    Some(limit) if items.len() > 1 => {
+                        let widths: usize = items.iter().map(|item| width(&item.to_string())).sum();
+                        widths + 2 * (items.len() - 1) <= limit
+                    }
+                    _ => true,
+                };
+                within && items.iter().all(Doc::is_short)
+            }
+        }
+    }
+}
+
+/// The whole fragment on one line
+impl fmt::Display for Doc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Doc::Text(ref text) => write!(f, "{}", text),
+            Doc::List {
+                ref head,
+                open,
+                ref items,
+                close,
+                ..
+            } => {
+                let rendered: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                // a one-element tuple needs its comma
+                let comma = if self.is_tuple() && items.len() == 1 { "," } else { "" };
+                write!(f, "{}{}{}{}{}", head, open, rendered.join(", "), comma, close)
+            }
+        }
+    }
+}
+
+/// Something which writes itself at the writer's indentation, such as a field
+pub(crate) trait Layout {
+    /// Write `self` followed by `suffix`, e.g. the `,` after a field
+    fn layout(&self, w: &mut Writer, suffix: &str);
+}
+
+pub(crate) struct Writer {
+    lines: Vec<String>,
+    indent: usize,
+}
+
+impl Writer {
+    pub(crate) fn new() -> Writer {
+        Writer {
+            lines: Vec::new(),
+            indent: 0,
+        }
+    }
+
+    /// Write a single line at the current indentation
+    pub(crate) fn line<S: AsRef<str>>(&mut self, line: S) {
+        let line = line.as_ref();
+        if line.is_empty() {
+            self.lines.push(String::new());
+        } else {
+            self.lines.push(format!("{}{}", INDENT.repeat(self.indent), line));
+        }
+    }
+
+    /// Write every line of `text` at the current indentation.
+    /// Nothing is written for empty text.
+    pub(crate) fn lines<S: AsRef<str>>(&mut self, text: S) {
+        for line in text.as_ref().lines() {
+            self.line(line);
+        }
+    }
+
+    /// Write `body` one level further indented
+    pub(crate) fn indented<F: FnOnce(&mut Writer)>(&mut self, body: F) {
+        self.indent += 1;
+        body(self);
+        self.indent -= 1;
+    }
+
+    /// A braced block such as a struct body. `members` are separated
+    /// by commas, with a trailing comma, and an empty block is `{}`.
+    pub(crate) fn block<T: Layout>(&mut self, header: &str, members: &[T]) {
+        if members.is_empty() {
+            self.line(format!("{} {{}}", header));
+            return;
+        }
+        self.line(format!("{} {{", header));
+        self.indented(|w| for member in members {
+            member.layout(w, ",");
+        });
+        self.line("}");
+    }
+
+    /// An attribute such as `#[derive(Debug)]`. If it is too long, derives
+    /// go on one line of their own if they fit and other lists get one
+    /// item per line. As in rustfmt, the attribute of a field or variant
+    /// leaves room for the `suffix` written after that member.
+    pub(crate) fn attribute(&mut self, name: &str, items: &[String], suffix: &str) {
+        let suffix = width(suffix);
+        let joined = items.join(", ");
+        let flat = format!("#[{}({})]", name, joined);
+        let one_line = if name == "derive" {
+            // rustfmt measures derives with a trailing comma and three
+            // more columns for the brackets
+            self.fits(&flat, suffix + 4)
+        } else {
+            self.fits(&flat, suffix) && (items.len() == 1 || width(&joined) <= ATTR_WIDTH)
+        };
+        if one_line {
+            self.line(flat);
+            return;
+        }
+        self.line(format!("#[{}(", name));
+        self.indented(|w| if name == "derive" {
+            // the trailing comma may pass the width
+            if w.fits(&joined, suffix) {
+                w.line(format!("{},", joined));
+            } else {
+                for item in items {
+                    w.line(format!("{},", item));
+                }
+            }
+        } else {
+            let (last, rest) = items.split_last().unwrap();
+            for item in rest {
+                w.line(format!("{},", item));
+            }
+            w.line(last);
+        });
+        self.line(")]");
+    }
+
+    /// Write `doc` followed by `suffix`, broken over several lines if
+    /// it does not fit on one
+    pub(crate) fn doc(&mut self, doc: &Doc, suffix: &str) {
+        let lines = layout(doc, self.indent, self.column(), width(suffix));
+        self.push(lines, suffix);
+    }
+
+    /// Write `lhs rhs` followed by `suffix`, as in `name: Type` or
+    /// `type Name = Type`. As in rustfmt, `rhs` moves to the next line
+    /// when that saves breaking it, or breaking it as much.
+    pub(crate) fn assign(&mut self, lhs: &str, rhs: &Doc, suffix: &str) {
+        let suffix_width = width(suffix);
+        let offset = self.column() + width(lhs) + 1;
+        let mut same = layout(rhs, self.indent, offset, suffix_width);
+        let same_fits = same.len() > 1 || offset + width(&same[0]) + suffix_width <= MAX_WIDTH;
+        if same.len() > 1 || !same_fits {
+            let next_column = self.column() + INDENT.len();
+            let next = layout(rhs, self.indent + 1, next_column, suffix_width);
+            let next_fits =
+                next.len() > 1 || next_column + width(&next[0]) + suffix_width <= MAX_WIDTH;
+            if next_fits && (!same_fits || prefer_next_line(&same, &next)) {
+                self.line(lhs);
+                self.indented(|w| w.push(next, suffix));
+                return;
+            }
+        }
+        same[0] = format!("{} {}", lhs, same[0]);
+        self.push(same, suffix);
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.lines.join("\n")
+    }
+
+    fn column(&self) -> usize {
+        self.indent * INDENT.len()
+    }
+
+    /// Whether `text` and `suffix` more columns fit on a line at the
+    /// current indentation
+    fn fits(&self, text: &str, suffix: usize) -> bool {
+        self.column() + width(text) + suffix <= MAX_WIDTH
+    }
+
+    /// Write lines from `layout`, whose first line is not yet indented
+    fn push(&mut self, mut lines: Vec<String>, suffix: &str) {
+        lines.last_mut().unwrap().push_str(suffix);
+        let mut lines = lines.into_iter();
+        self.line(lines.next().unwrap());
+        self.lines.extend(lines);
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Lay out `doc` starting at column `offset` of a line in a block `indent`
+/// levels deep, leaving `suffix` columns after it. Lines after the first
+/// are indented; the first is not.
+fn layout(doc: &Doc, indent: usize, offset: usize, suffix: usize) -> Vec<String> {
+    let flat = doc.to_string();
+    if doc.is_short() && offset + width(&flat) + suffix <= MAX_WIDTH {
+        return vec![flat];
+    }
+    let (head, open, items, close, overflow) = match *doc {
+        Doc::List {
+            ref head,
+            open,
+            ref items,
+            close,
+            overflow,
+            ..
+        } if !items.is_empty() => (head, open, items, close, overflow),
+        _ => return vec![flat],
+    };
+    let first = format!("{}{}", head, open);
+    if overflow && items.len() == 1 && items[0].is_tuple() {
+        let mut lines = layout(&items[0], indent, offset + width(&first), suffix + width(close));
+        lines[0] = format!("{}{}", first, lines[0]);
+        lines.last_mut().unwrap().push_str(close);
+        return lines;
+    }
+    let mut lines = vec![first];
+    let inner = INDENT.repeat(indent + 1);
+    for item in items {
+        let mut item_lines = layout(item, indent + 1, inner.len(), 1);
+        item_lines[0] = format!("{}{}", inner, item_lines[0]);
+        item_lines.last_mut().unwrap().push(',');
+        lines.extend(item_lines);
+    }
+    lines.push(format!("{}{}", INDENT.repeat(indent), close));
+    lines
+}
+
+/// rustfmt's choice between a broken right hand side on the same line and
+/// one starting on the next line
+fn prefer_next_line(same: &[String], next: &[String]) -> bool {
+    let opens_paren = |lines: &[String]| lines[0].ends_with('(');
+    next.len() == 1 || same.len() > next.len() + 1 || opens_paren(same) && !opens_paren(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Member(&'static str);
+
+    impl Layout for Member {
+        fn layout(&self, w: &mut Writer, suffix: &str) {
+            w.lines(format!("{}{}", self.0, suffix));
+        }
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut w = Writer::new();
+        w.lines("#[derive(Debug)]\n#[repr(C)]");
+        w.lines("");
+        w.block("pub struct Foo", &[Member("#[serde(default)]\nx: u32"), Member("y: u8")]);
+        w.block("struct Empty", &Vec::<Member>::new());
+        let expect = "#[derive(Debug)]
+#[repr(C)]
+pub struct Foo {
+    #[serde(default)]
+    x: u32,
+    y: u8,
+}
+struct Empty {}";
+        assert_eq!(w.finish(), expect);
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let named = |name: &str| Doc::Text(name.into());
+        let long = |n: usize| Doc::Text(format!("Long{}", "x".repeat(n)));
+        let mut w = Writer::new();
+        let derives: Vec<String> = (0..10).map(|ix| format!("Derive{}", ix)).collect();
+        w.attribute("derive", &derives, "");
+        let derives: Vec<String> = (0..3).map(|_| format!("Derive{}", "x".repeat(40))).collect();
+        w.attribute("derive", &derives, "");
+        w.attribute("serde", &["rename_all = \"camelCase\"".into(), "x".repeat(80)], "");
+        w.indented(|w| {
+            w.assign("short:", &Doc::generic("Vec", vec![named("u8")]), ",");
+            w.assign("next_line:", &Doc::generic("Vec", vec![long(80)]), ",");
+            w.assign("broken:", &Doc::generic("Result", vec![long(50), long(50)]), ",");
+            let tuple = Doc::tuple(vec![long(50), long(50)]);
+            w.assign("overflow:", &Doc::generic("Vec", vec![tuple.clone()]), ",");
+            w.doc(&Doc::tuple_struct("Variant", vec![tuple]), ",");
+        });
+        let expect = format!(
+            "#[derive(
+    Derive0, Derive1, Derive2, Derive3, Derive4, Derive5, Derive6, Derive7, Derive8, Derive9,
+)]
+#[derive(
+    Derive{d},
+    Derive{d},
+    Derive{d},
+)]
+#[serde(
+    rename_all = \"camelCase\",
+    {x}
+)]
+    short: Vec<u8>,
+    next_line:
+        Vec<Long{x}>,
+    broken: Result<
+        Long{l},
+        Long{l},
+    >,
+    overflow: Vec<(
+        Long{l},
+        Long{l},
+    )>,
+    Variant(
+        (
+            Long{l},
+            Long{l},
+        ),
+    ),",
+            d = "x".repeat(40),
+            x = "x".repeat(80),
+            l = "x".repeat(50)
+        );
+        assert_eq!(w.finish(), expect);
+    }
+}
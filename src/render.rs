@@ -0,0 +1,168 @@
+//! Rendering a whole `ItemMap` as a source file
+
+use std::collections::BTreeSet;
+
+use {Id, ItemMap};
+
+const DEFAULT_BANNER: &str = "This file is generated by simple-codegen. Do not edit it by hand.";
+
+/// The order in which items are written out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Order {
+    /// Every item after the items it refers to
+    Dependency,
+    /// Sorted by Id
+    #[default]
+    Alphabetical,
+    /// The order the items were given to `ItemMap::build`
+    Insertion,
+}
+
+/// Options for `ItemMap::render`. The output only depends on the map and
+/// the options, so it is identical from one run to the next.
+///
+/// # Example
+///
+/// ```ignore
+/// let source = map.render(
+///     &RenderOptions::default()
+///         .order(Order::Dependency)
+///         .allow(&["dead_code", "non_camel_case_types"]),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    order: Order,
+    banner: Option<String>,
+    allow: BTreeSet<String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            order: Order::default(),
+            banner: Some(DEFAULT_BANNER.into()),
+            allow: BTreeSet::new(),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Replace the comment at the top of the file. Pass None to omit it.
+    pub fn banner(mut self, banner: Option<&str>) -> Self {
+        self.banner = banner.map(String::from);
+        self
+    }
+
+    /// Add lints to a crate-level `#![allow(...)]`
+    pub fn allow(mut self, lints: &[&str]) -> Self {
+        for lint in lints {
+            self.allow.insert(lint.to_string());
+        }
+        self
+    }
+
+    pub(crate) fn render(&self, map: &ItemMap) -> String {
+        let mut out = String::new();
+        if let Some(ref banner) = self.banner {
+            for line in banner.lines() {
+                if line.is_empty() {
+                    out.push_str("//\n");
+                } else {
+                    out.push_str(&format!("// {}\n", line));
+                }
+            }
+            out.push('\n');
+        }
+        if !self.allow.is_empty() {
+            let lints: Vec<&str> = self.allow.iter().map(|lint| &**lint).collect();
+            out.push_str(&format!("#![allow({})]\n\n", lints.join(", ")));
+        }
+        let graph;
+        let ids: Vec<&Id> = match self.order {
+            Order::Dependency => {
+                graph = map.dependency_graph();
+                graph.topological_order()
+            }
+            Order::Alphabetical => map.iter().map(|(id, _)| id).collect(),
+            Order::Insertion => map.insertion_order().iter().collect(),
+        };
+        let items: Vec<String> = ids.into_iter()
+            .map(|id| map.get(id).unwrap().to_string().trim().to_string())
+            .collect();
+        out.push_str(&items.join("\n\n"));
+        if !items.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Alias, Attributes, Derive, Field, Item, NewType, Struct, Type, TypeId, ValueId,
+         Visibility};
+
+    fn items() -> Vec<Box<Item>> {
+        vec![
+            Box::new(Struct::new(
+                TypeId::new("Order").unwrap(),
+                Visibility::Public,
+                Attributes::default().derive(&[Derive::Debug]),
+                vec![Field::new(ValueId::new("sku").unwrap(), Type::named("Sku").unwrap(), vec![])],
+            ).unwrap()),
+            Box::new(NewType::new(
+                TypeId::new("Sku").unwrap(),
+                Visibility::Public,
+                Attributes::default(),
+                Type::named("Code").unwrap(),
+            )),
+            Box::new(Alias::new(
+                TypeId::new("Code").unwrap(),
+                Visibility::Private,
+                Type::Primitive(::Primitive::String),
+            )),
+        ]
+    }
+
+    #[test]
+    fn test_render() {
+        let map = ItemMap::build(items()).unwrap();
+        let ordered = |order| {
+            let source = map.render(&RenderOptions::default().order(order).banner(None));
+            let mut decls = vec!["type Code", "struct Order", "struct Sku"];
+            decls.sort_by_key(|decl| source.find(decl).unwrap());
+            decls
+        };
+        assert_eq!(ordered(Order::Dependency), vec!["type Code", "struct Sku", "struct Order"]);
+        assert_eq!(ordered(Order::Alphabetical), vec!["type Code", "struct Order", "struct Sku"]);
+        assert_eq!(ordered(Order::Insertion), vec!["struct Order", "struct Sku", "type Code"]);
+
+        let options = RenderOptions::default()
+            .order(Order::Dependency)
+            .allow(&["non_camel_case_types", "dead_code"]);
+        let expect = "// This file is generated by simple-codegen. Do not edit it by hand.
+
+#![allow(dead_code, non_camel_case_types)]
+
+type Code = String;
+
+pub struct Sku(Code);
+
+#[derive(Debug)]
+pub struct Order {
+    sku: Sku,
+}
+";
+        let source = map.render(&options);
+        assert_eq!(source, expect);
+        let again = ItemMap::build(items()).unwrap();
+        assert_eq!(again.render(&options), source);
+    }
+}
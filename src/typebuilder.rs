@@ -1,24 +1,36 @@
 use std::fmt;
+use std::mem;
 use errors::*;
-use Id;
-use items::ItemMap;
+use pretty::Doc;
+use {Derive, Id, Lifetime, Path, TypeId};
 
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Type {
     Primitive(Primitive),
     Box(Box<Type>),
     Vec(Box<Type>),
     Option(Box<Type>),
     Result(Box<Type>, Box<Type>),
-    Map(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    Tuple(Vec<Type>),
     Named(Id),
-    Ref(Box<Type>),
+    /// A type outside the map, referred to by its path. The derives it
+    /// implements are unknown, so it prevents any from being inferred.
+    Path(Path),
+    Ref(Option<Lifetime>, Box<Type>),
 }
 
 impl Type {
+    /// A reference to a type by name, which must be UpperCamelCase.
+    /// Names containing `::`, such as `serde_json::Value`, become a `Path`.
     pub fn named<I: Into<String>>(name: I) -> Result<Type> {
-        Ok(Type::Named(Id::new(name)?))
+        let name = name.into();
+        if name.contains("::") {
+            Ok(Type::Path(Path::new(&name)?))
+        } else {
+            Ok(Type::Named(TypeId::new(name)?.into()))
+        }
     }
 
     pub fn optional(self, opt: bool) -> Type {
@@ -30,74 +42,172 @@ impl Type {
     }
 
     fn render(&self) -> String {
+        self.doc().to_string()
+    }
+
+    /// The type as a `Doc`, so that it can be broken over several lines
+    pub(crate) fn doc(&self) -> Doc {
         use self::Type::*;
         match *self {
-            Primitive(ref primitive) => primitive.native().to_string(),
-            Box(ref tb) => format!("Box<{}>", tb.render()),
-            Vec(ref tb) => format!("Vec<{}>", tb.render()),
-            Option(ref tb) => format!("Option<{}>", tb.render()),
-            Result(ref tb1, ref tb2) => format!("Result<{}, {}>", tb1.render(), tb2.render()),
-            Map(ref tb) => format!("Map<String, {}>", tb.render()),
-            Named(ref name) => name.to_string(),
-            Ref(ref tb) => format!("&{}", tb.render()),
+            Primitive(ref primitive) => Doc::Text(primitive.native().to_string()),
+            Box(ref tb) => Doc::generic("Box", vec![tb.doc()]),
+            Vec(ref tb) => Doc::generic("Vec", vec![tb.doc()]),
+            Option(ref tb) => Doc::generic("Option", vec![tb.doc()]),
+            Result(ref tb1, ref tb2) => Doc::generic("Result", vec![tb1.doc(), tb2.doc()]),
+            Map(ref tk, ref tv) => Doc::generic("Map", vec![tk.doc(), tv.doc()]),
+            Tuple(ref tbs) => Doc::tuple(tbs.iter().map(Type::doc).collect()),
+            Named(ref name) => Doc::Text(name.to_string()),
+            Path(ref path) if path.args().is_empty() => Doc::Text(path.to_string()),
+            Path(ref path) => {
+                let head = path.clone().with_args(vec![]).to_string();
+                Doc::generic(head, path.args().iter().map(Type::doc).collect())
+            }
+            Ref(Some(ref lifetime), ref tb) => tb.doc().prefixed(&format!("&{} ", lifetime)),
+            Ref(None, ref tb) => tb.doc().prefixed("&"),
+        }
+    }
+
+    /// The `Named` and `Path` types which must implement `derive` for this
+    /// type to derive it too, or None if this type can never derive it.
+    /// `Map` is assumed to be a `HashMap`, so cannot be `Copy`, `Hash`,
+    /// `PartialOrd` or `Ord`.
+    pub(crate) fn derive_deps(&self, derive: &Derive) -> Option<Vec<&Type>> {
+        use self::Type::*;
+        match (self, derive) {
+            (_, Derive::Custom(_)) => None,
+            (Primitive(p), _) => {
+                if p.derives(derive) {
+                    Some(vec![])
+                } else {
+                    None
+                }
+            }
+            (Box(_), Derive::Copy) => None,
+            (Box(tb), _) => tb.derive_deps(derive),
+            (Vec(_), Derive::Copy) => None,
+            (Vec(_), Derive::Default) => Some(vec![]),
+            (Vec(tb), _) => tb.derive_deps(derive),
+            (Option(_), Derive::Default) => Some(vec![]),
+            (Option(tb), _) => tb.derive_deps(derive),
+            (Map(_, _), Derive::Copy | Derive::Hash | Derive::PartialOrd | Derive::Ord) => None,
+            (Map(_, _), Derive::Default) => Some(vec![]),
+            (Map(tk, tv), _) => derive_both(tk, tv, derive),
+            (Result(_, _), Derive::Default) => None,
+            (Result(tb1, tb2), _) => derive_both(tb1, tb2, derive),
+            (Tuple(tbs), _) => {
+                let mut deps = vec![];
+                for tb in tbs {
+                    deps.extend(tb.derive_deps(derive)?);
+                }
+                Some(deps)
+            }
+            (Named(_), _) | (Path(_), _) => Some(vec![self]),
+            (Ref(_, _), Derive::Copy | Derive::Clone) => Some(vec![]),
+            (Ref(_, _), Derive::Default) => None,
+            (Ref(_, tb), _) => tb.derive_deps(derive),
         }
     }
 
-    /// Dereference the Type until we either get to Named or
-    /// Primitive, then return the Id or None
-    pub(crate) fn get_named_root(&self) -> Option<&Id> {
+    /// Ids which are stored inline in this type, rather than behind a pointer
+    pub(crate) fn unboxed_ids(&self) -> Vec<&Id> {
         use self::Type::*;
         match *self {
-            Primitive(_) => None,
-            Box(ref tb) => tb.get_named_root(),
-            Vec(ref tb) => tb.get_named_root(),
-            Option(ref tb) => tb.get_named_root(),
-            Result(ref tb1, ref tb2) => tb1.get_named_root(), // FIXME discard tb2?
-            Map(ref tb) => tb.get_named_root(),
-            Named(ref name) => Some(name),
-            Ref(ref tb) => tb.get_named_root(),
+            Option(ref tb) => tb.unboxed_ids(),
+            Result(ref tb1, ref tb2) => {
+                let mut ids = tb1.unboxed_ids();
+                ids.extend(tb2.unboxed_ids());
+                ids
+            }
+            Tuple(ref tbs) => tbs.iter().flat_map(|tb| tb.unboxed_ids()).collect(),
+            Named(ref name) => vec![name],
+            // how an external type holds its arguments is unknown, so
+            // they are assumed to be behind a pointer as in a `Vec`
+            Primitive(_) | Path(_) => vec![],
+            Ref(_, _) => vec![],
+            Box(_) => vec![],
+            Vec(_) => vec![],
+            Map(_, _) => vec![],
         }
     }
 
-    pub(crate) fn is_defaultable(&self, map: &ItemMap) -> bool {
+    /// Every Id referred to anywhere within this type, in order of appearance
+    pub(crate) fn named_ids(&self) -> Vec<&Id> {
         use self::Type::*;
         match *self {
-            Primitive(_) => true,
-            Box(ref tb) => tb.is_defaultable(map),
-            Vec(_) => true,
-            Option(_) => true,
-            Map(_) => true,
-            Result(_, _) => false,
-            Named(ref name) => {
-                map.get(name)
-                    .map(|item| item.is_defaultable(&map))
-                    .unwrap_or(false)
+            Primitive(_) => vec![],
+            Path(ref path) => path.args().iter().flat_map(|tb| tb.named_ids()).collect(),
+            Box(ref tb) | Vec(ref tb) | Option(ref tb) | Ref(_, ref tb) => tb.named_ids(),
+            Result(ref tb1, ref tb2) | Map(ref tb1, ref tb2) => {
+                let mut ids = tb1.named_ids();
+                ids.extend(tb2.named_ids());
+                ids
             }
-            Ref(_) => false,
+            Tuple(ref tbs) => tbs.iter().flat_map(|tb| tb.named_ids()).collect(),
+            Named(ref name) => vec![name],
         }
     }
 
-    pub(crate) fn contains_unboxed_id(&self, id: &Id, map: &ItemMap) -> bool {
+    /// Replace every Id within this type for which `rename` returns a new one
+    pub(crate) fn rename_ids(&mut self, rename: &dyn Fn(&Id) -> Option<Id>) {
         use self::Type::*;
         match *self {
-            Option(ref tb) => tb.contains_unboxed_id(id, map),
-            Map(ref tb) => tb.contains_unboxed_id(id, map),
-            Result(ref tb1, ref tb2) => {
-                tb1.contains_unboxed_id(id, map) && tb2.contains_unboxed_id(id, map)
+            Primitive(_) => {}
+            Path(ref mut path) => {
+                for tb in path.args_mut() {
+                    tb.rename_ids(rename);
+                }
+            }
+            Box(ref mut tb) | Vec(ref mut tb) | Option(ref mut tb) | Ref(_, ref mut tb) => {
+                tb.rename_ids(rename)
+            }
+            Result(ref mut tb1, ref mut tb2) | Map(ref mut tb1, ref mut tb2) => {
+                tb1.rename_ids(rename);
+                tb2.rename_ids(rename);
             }
-            Named(ref name) => {
-                map.get(name)
-                    .map(|item| item.contains_unboxed_id(id, map))
-                    .unwrap_or(false)
+            Tuple(ref mut tbs) => {
+                for tb in tbs {
+                    tb.rename_ids(rename);
+                }
             }
-            Primitive(_) => false,
-            Ref(_) => false,
-            Box(_) => false,
-            Vec(_) => false,
+            Named(ref mut name) => {
+                if let Some(new) = rename(name) {
+                    *name = new;
+                }
+            }
+        }
+    }
+
+    /// Put every unboxed occurrence of `id` behind a `Box`
+    pub(crate) fn box_id(&mut self, id: &Id) {
+        use self::Type::*;
+        let found = match *self {
+            Option(ref mut tb) => return tb.box_id(id),
+            Result(ref mut tb1, ref mut tb2) => {
+                tb1.box_id(id);
+                return tb2.box_id(id);
+            }
+            Tuple(ref mut tbs) => {
+                for tb in tbs {
+                    tb.box_id(id);
+                }
+                return;
+            }
+            Named(ref name) => name == id,
+            _ => false,
+        };
+        if found {
+            let named = mem::replace(self, Primitive(self::Primitive::Null));
+            *self = Box(::std::boxed::Box::new(named));
         }
     }
 }
 
+fn derive_both<'a>(tb1: &'a Type, tb2: &'a Type, derive: &Derive) -> Option<Vec<&'a Type>> {
+    let mut deps = tb1.derive_deps(derive)?;
+    deps.extend(tb2.derive_deps(derive)?);
+    Some(deps)
+}
+
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.render())
@@ -105,24 +215,53 @@ impl fmt::Display for Type {
 }
 
 /// Represents a primitive Rust type
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum Primitive {
     Null,
     Boolean,
+    I8,
+    I16,
+    I32,
     I64,
+    U8,
+    U16,
+    U32,
+    F32,
     F64,
     String,
+    /// Unsized `str`, only useful behind a `Type::Ref`
+    Str,
 }
 
 impl Primitive {
+    fn derives(&self, derive: &Derive) -> bool {
+        use self::Primitive::*;
+        !matches!(
+            (*self, derive),
+            (_, Derive::Custom(_)) |
+                (F32, Derive::Eq | Derive::Hash | Derive::Ord) |
+                (F64, Derive::Eq | Derive::Hash | Derive::Ord) |
+                (String, Derive::Copy) |
+                (Str, Derive::Copy | Derive::Clone | Derive::Default)
+        )
+    }
+
     fn native(&self) -> &str {
         use self::Primitive::*;
         match *self {
             Null => "()",
             Boolean => "bool",
+            I8 => "i8",
+            I16 => "i16",
+            I32 => "i32",
             I64 => "i64",
+            U8 => "u8",
+            U16 => "u16",
+            U32 => "u32",
+            F32 => "f32",
             F64 => "f64",
             String => "String",
+            Str => "str",
         }
     }
 }
@@ -143,8 +282,9 @@ mod tests {
         let typ = Type::Box(Box::new(Type::Result(
             Box::new(Type::Named(Id::new("ResultLeft").unwrap())),
             Box::new(Type::Map(
+                Box::new(Type::Primitive(Primitive::String)),
                 Box::new(Type::Vec(Box::new(Type::Option(Box::new(
-                    Type::Ref(Box::new(Type::Primitive(Primitive::String))),
+                    Type::Ref(None, Box::new(Type::Primitive(Primitive::String))),
                 ))))),
             )),
         )));
@@ -152,5 +292,35 @@ mod tests {
             typ.render(),
             "Box<Result<ResultLeft, Map<String, Vec<Option<&String>>>>>"
         );
+        let tuple = Type::Tuple(vec![
+            Type::named("Left").unwrap(),
+            Type::Result(
+                Box::new(Type::named("Ok").unwrap()),
+                Box::new(Type::named("Err").unwrap()),
+            ),
+        ]);
+        assert_eq!(tuple.render(), "(Left, Result<Ok, Err>)");
+        let names: Vec<&str> = tuple.named_ids().into_iter().map(|id| &**id).collect();
+        assert_eq!(names, vec!["Left", "Ok", "Err"]);
+    }
+
+    #[test]
+    fn test_named_conventions() {
+        assert!(Type::named("my_field").is_err());
+        assert!(Type::named("serde_json::value").is_err());
+        let value = Type::named("serde_json::Value").unwrap();
+        assert_eq!(value, Type::Path(Path::new("serde_json::Value").unwrap()));
+        assert!(value.named_ids().is_empty());
+        assert_eq!(value.derive_deps(&Derive::Debug), Some(vec![&value]));
+        let path = Path::new("chrono::DateTime").unwrap();
+        let mut datetime = Type::Path(path.with_args(vec![Type::named("Utc").unwrap()]));
+        assert_eq!(datetime.render(), "chrono::DateTime<Utc>");
+        assert_eq!(datetime.named_ids(), vec![&Id::new("Utc").unwrap()]);
+        datetime.rename_ids(&|_| Some(Id::new("Local").unwrap()));
+        assert_eq!(datetime.render(), "chrono::DateTime<Local>");
+
+        let lifetime = Lifetime::static_();
+        let typ = Type::Ref(Some(lifetime), Box::new(Type::Primitive(Primitive::Str)));
+        assert_eq!(typ.render(), "&'static str");
     }
 }
@@ -1,50 +1,132 @@
 use std::borrow::Cow;
-use std::collections::BTreeSet;
+use std::collections::BTreeMap;
 
-use keywords;
+use deunicode::deunicode;
+use unicode_xid::UnicodeXID;
+
+use keywords::{self, Edition, KeywordKind};
 use errors::*;
+use {Backend, FormatOptions, Formatter, KeywordEscape};
 
 lazy_static! {
-    static ref RUST_KEYWORDS: BTreeSet<&'static str> = {
-        keywords::RUST_KEYWORDS.iter().map(|v| *v).collect()
+    static ref RUST_KEYWORDS: BTreeMap<&'static str, Vec<(KeywordKind, Edition)>> = {
+        let mut map = BTreeMap::new();
+        for &(word, kind, since) in keywords::RUST_KEYWORDS {
+            map.entry(word).or_insert_with(Vec::new).push((kind, since));
+        }
+        map
     };
 }
 
-pub(crate) fn validate_identifier(ident: &str) -> Result<()> {
-    // This assumes ASCII character set
+/// The kind of keyword `word` is in `edition`, if it is one at all
+pub fn keyword_kind(word: &str, edition: Edition) -> Option<KeywordKind> {
+    RUST_KEYWORDS.get(word).and_then(|kinds| {
+        kinds
+            .iter()
+            .rev()
+            .find(|&&(_, since)| since <= edition)
+            .map(|&(kind, _)| kind)
+    })
+}
+
+/// Whether `word` is a strict or reserved keyword in `edition`, and so
+/// can only be used as a raw identifier
+pub fn is_keyword(word: &str, edition: Edition) -> bool {
+    match keyword_kind(word, edition) {
+        Some(KeywordKind::Strict) | Some(KeywordKind::Reserved) => true,
+        Some(KeywordKind::Weak) | None => false,
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c == '_' || UnicodeXID::is_xid_start(c)
+}
+
+pub(crate) fn is_ident_continue(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c)
+}
+
+/// Keywords which cannot be used as raw identifiers
+const NOT_RAW: &[&str] = &["self", "Self", "super", "crate", "_"];
+
+/// Check that `ident` is a valid identifier, following the Unicode
+/// `XID_Start`/`XID_Continue` rules that Rust uses. Raw identifiers
+/// such as `r#type` are accepted. Keywords are those of `edition`.
+pub(crate) fn validate_identifier(ident: &str, edition: Edition) -> Result<()> {
+    if let Some(bare) = ident.strip_prefix("r#") {
+        if NOT_RAW.contains(&bare) {
+            bail!("'{}' cannot be a raw identifier", bare)
+        }
+        if bare.is_empty() {
+            bail!("Raw identifier '{}' is empty", ident)
+        }
+        // only one `r#` is stripped, so `r#r#x` is rejected
+        return validate_characters(bare);
+    }
     if ident == "_" {
         bail!("'_' is not a valid item name")
     }
     if ident.len() == 0 {
         bail!("Identifier is empty string")
     }
-    if RUST_KEYWORDS.contains(ident) {
+    if is_keyword(ident, edition) {
         bail!("Identifier '{}' is a Rust keyword", ident)
     }
+    validate_characters(ident)
+}
+
+fn validate_characters(ident: &str) -> Result<()> {
     let mut is_leading_char = true;
     for (ix, c) in ident.chars().enumerate() {
         if is_leading_char {
-            match c {
-                'A'...'Z' | 'a'...'z' | '_' => {
-                    is_leading_char = false;
-                }
-                _ => bail!("Identifier has invalid character at index {}: '{}'", ix, c),
-            }
-        } else {
-            match c {
-                'A'...'Z' | 'a'...'z' | '_' | '0'...'9' => {}
-                _ => bail!("Identifier has invalid character at index {}: '{}'", ix, c),
+            if !is_ident_start(c) {
+                bail!("Identifier has invalid character at index {}: '{}'", ix, c)
             }
+            is_leading_char = false;
+        } else if !is_ident_continue(c) {
+            bail!("Identifier has invalid character at index {}: '{}'", ix, c)
         }
     }
     Ok(())
 }
 
-pub(crate) fn make_valid_identifier(ident: &str) -> Result<Cow<str>> {
+/// Whether `ident` follows the UpperCamelCase convention for types,
+/// as checked by the `non_camel_case_types` lint
+pub(crate) fn is_upper_camel_case(ident: &str) -> bool {
+    let ident = ident.trim_matches('_');
+    let chars: Vec<char> = ident.chars().collect();
+    let has_case = |c: char| c.is_uppercase() || c.is_lowercase();
+    match chars.first() {
+        Some(first) if first.is_lowercase() => return false,
+        _ => (),
+    }
+    !ident.contains("__") &&
+        !chars.windows(2).any(|pair| {
+            (has_case(pair[0]) && pair[1] == '_') || (pair[0] == '_' && has_case(pair[1]))
+        })
+}
+
+/// Whether `ident` follows the snake_case convention for fields and
+/// functions, as checked by the `non_snake_case` lint
+pub(crate) fn is_snake_case(ident: &str) -> bool {
+    let ident = ident.trim_matches('_');
+    !ident.contains("__") && !ident.chars().any(char::is_uppercase)
+}
+
+/// Whether `ident` follows the SCREAMING_SNAKE_CASE convention for
+/// consts and statics, as checked by the `non_upper_case_globals` lint
+pub(crate) fn is_screaming_snake_case(ident: &str) -> bool {
+    !ident.chars().any(char::is_lowercase)
+}
+
+pub(crate) fn make_valid_identifier(
+    ident: &str,
+    escape: KeywordEscape,
+    edition: Edition,
+) -> Result<Cow<'_, str>> {
     // strip out invalid characters and ensure result is valid
     // bit ugly to reallocate but at least it is simple
-    // TODO use unicode XID_start/XID_continue
-    if let Ok(()) = validate_identifier(ident) {
+    if let Ok(()) = validate_identifier(ident, edition) {
         // happy path
         return Ok(Cow::Borrowed(ident))
     }
@@ -52,22 +134,20 @@ pub(crate) fn make_valid_identifier(ident: &str) -> Result<Cow<str>> {
     let mut is_leading_char = true;
     for c in ident.chars() {
         if is_leading_char {
-            match c {
-                'A'...'Z' | 'a'...'z' | '_' => {
-                    is_leading_char = false;
-                    out.push(c);
-                }
-                _ => (),
-            }
-        } else {
-            match c {
-                'A'...'Z' | 'a'...'z' | '_' | '0'...'9' => out.push(c),
-                _ => (),
+            if is_ident_start(c) {
+                is_leading_char = false;
+                out.push(c);
             }
+        } else if is_ident_continue(c) {
+            out.push(c)
         }
     }
-    if RUST_KEYWORDS.contains(&*out) {
-        out.push('_')
+    if is_keyword(&out, edition) {
+        if escape == KeywordEscape::Raw && !NOT_RAW.contains(&&*out) {
+            out.insert_str(0, "r#");
+        } else {
+            out.push('_')
+        }
     };
     if out.len() == 0 || out == "_" {
         bail!("could not generate valid identifier from {}", ident)
@@ -75,75 +155,172 @@ pub(crate) fn make_valid_identifier(ident: &str) -> Result<Cow<str>> {
     Ok(Cow::Owned(out))
 }
 
-pub fn rust_format(code: &str) -> Result<String> {
-    use rustfmt::{Input, format_input};
-    use std::fs::File;
-    use tempdir::TempDir;
-    use std::io::prelude::*;
+/// As `make_valid_identifier`, but first transliterating any non-ASCII
+/// characters so the result is plain ASCII (`größe` becomes `grosse`)
+pub(crate) fn make_valid_ascii_identifier(
+    ident: &str,
+    escape: KeywordEscape,
+    edition: Edition,
+) -> Result<Cow<'_, str>> {
+    if ident.is_ascii() {
+        return make_valid_identifier(ident, escape, edition);
+    }
+    let ascii = deunicode(ident);
+    let valid = make_valid_identifier(&ascii, escape, edition)
+        .chain_err(|| format!("could not generate valid identifier from {}", ident))?
+        .into_owned();
+    Ok(Cow::Owned(valid))
+}
 
-    let tmpdir = TempDir::new("codegen-rustfmt")?;
-    let tmppath = tmpdir.path().join("to_format.rs");
+/// Format Rust source with the `rustfmt` binary on the `PATH`. Fails with
+/// `ErrorKind::Syntax` if `code` does not parse. See `Formatter` for the
+/// builtin backend, which needs no `rustfmt`, and `rust_format_with` for
+/// style settings.
+pub fn rust_format(code: &str) -> Result<String> {
+    rust_format_with(code, &FormatOptions::default())
+}
 
-    // FIXME workaround is necessary until rustfmt works programmatically
-    {
-        let mut tmp = File::create(&tmppath)?;
-        tmp.write_all(code.as_bytes())?;
-    }
-    let input = Input::File((&tmppath).into());
-    let mut fakebuf = Vec::new(); // pretty weird that this is necessary.. but it is
+/// As `rust_format`, with style settings such as `hard_tabs` or a
+/// `rustfmt.toml`
+pub fn rust_format_with(code: &str, options: &FormatOptions) -> Result<String> {
+    Formatter::default().backend(Backend::Rustfmt(None)).options(options.clone()).format(code)
+}
 
-    match format_input(input, &Default::default(), Some(&mut fakebuf)) {
-        Ok((_summmary, _filemap, _report)) => {}
-        Err((e, _summary)) => Err(e)?,
-    }
+/// Whether a `rustfmt` binary is on the `PATH`. Tests which need one
+/// skip those checks without it.
+#[cfg(test)]
+pub(crate) fn has_rustfmt() -> bool {
+    ::std::process::Command::new("rustfmt").arg("--version").output().is_ok()
+}
 
-    let mut tmp = File::open(&tmppath)?;
-    let mut buf = String::new();
-    tmp.read_to_string(&mut buf)?;
-    // FIXME this error will trigger if the input is *correctly* unchanged
-    if buf == code {
-        bail!("Syntax error detected")
+/// `item` as rendered, with a final newline. When `rustfmt` is on the
+/// `PATH`, this also checks that it would leave the output unchanged.
+#[cfg(test)]
+pub(crate) fn rendered<T: ::std::fmt::Display + ?Sized>(item: &T) -> String {
+    let rendered = format!("{}\n", item);
+    if has_rustfmt() {
+        assert_eq!(rust_format(&rendered).unwrap(), rendered);
     }
-    Ok(buf)
+    rendered
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn validate(ident: &str) -> Result<()> {
+        validate_identifier(ident, Edition::default())
+    }
+
+    fn make_valid(ident: &str) -> Result<Cow<'_, str>> {
+        make_valid_identifier(ident, KeywordEscape::Underscore, Edition::default())
+    }
+
+    fn make_valid_ascii(ident: &str) -> Result<Cow<'_, str>> {
+        make_valid_ascii_identifier(ident, KeywordEscape::Underscore, Edition::default())
+    }
+
     #[test]
     fn test_validate_ident() {
-        assert!(validate_identifier("thisIsValid").is_ok());
-        assert!(validate_identifier("_this_also_valid").is_ok());
-        assert!(validate_identifier("_type").is_ok());
-        assert!(validate_identifier("T343434234").is_ok());
+        assert!(validate("thisIsValid").is_ok());
+        assert!(validate("_this_also_valid").is_ok());
+        assert!(validate("_type").is_ok());
+        assert!(validate("T343434234").is_ok());
 
-        assert!(validate_identifier("_").is_err());
-        assert!(validate_identifier("@").is_err());
-        assert!(validate_identifier("contains space").is_err());
-        assert!(validate_identifier("contains££££symbol").is_err());
-        assert!(validate_identifier("type").is_err());
-        assert!(validate_identifier("3_invalid").is_err());
+        assert!(validate("_").is_err());
+        assert!(validate("@").is_err());
+        assert!(validate("contains space").is_err());
+        assert!(validate("contains££££symbol").is_err());
+        assert!(validate("type").is_err());
+        assert!(validate("3_invalid").is_err());
+        assert!(validate("r#type").is_ok());
+        assert!(validate("r#not_keyword").is_ok());
+        assert!(validate("r#r#x").is_err());
+        assert!(validate("r#r#type").is_err());
+        assert!(validate("r#").is_err());
+        assert!(validate("r#self").is_err());
+        assert!(validate("r#3").is_err());
     }
 
     #[test]
     fn test_make_valid_identifier() {
         let id1 = "1234_abcd".into();
-        assert_eq!(make_valid_identifier(id1).unwrap(), "_abcd");
+        assert_eq!(make_valid(id1).unwrap(), "_abcd");
         let id2 = "$1234Abcd".into();
-        assert_eq!(make_valid_identifier(id2).unwrap(), "Abcd");
+        assert_eq!(make_valid(id2).unwrap(), "Abcd");
         let id3 = "$@1234\\|./".into();
-        assert!(make_valid_identifier(id3).is_err());
+        assert!(make_valid(id3).is_err());
         let id4 = "1234_".into();
-        assert!(make_valid_identifier(id4).is_err());
+        assert!(make_valid(id4).is_err());
         let id5 = "".into();
-        assert!(make_valid_identifier(id5).is_err());
+        assert!(make_valid(id5).is_err());
         let id6 = "_".into();
-        assert!(make_valid_identifier(id6).is_err());
+        assert!(make_valid(id6).is_err());
         let id7 = "type".into();
-        assert_eq!(make_valid_identifier(id7).unwrap(), "type_");
+        assert_eq!(make_valid(id7).unwrap(), "type_");
         let id8 = "this 123".into();
-        assert_eq!(make_valid_identifier(id8).unwrap(), "this123");
+        assert_eq!(make_valid(id8).unwrap(), "this123");
+    }
+
+    #[test]
+    fn test_raw_identifier() {
+        let raw = |ident| {
+            make_valid_identifier(ident, KeywordEscape::Raw, Edition::default()).unwrap()
+        };
+        assert_eq!(raw("type"), "r#type");
+        assert_eq!(raw("r#type"), "r#type");
+        assert_eq!(raw("$match"), "r#match");
+        assert_eq!(raw("self"), "self_");
+        assert_eq!(raw("Self"), "Self_");
+        assert_eq!(raw("super"), "super_");
+        assert_eq!(raw("crate"), "crate_");
+        assert_eq!(raw("valid"), "valid");
+    }
+
+    #[test]
+    fn test_unicode_identifier() {
+        assert!(validate("größe").is_ok());
+        assert!(validate("名前").is_ok());
+        assert!(validate("_ñ").is_ok());
+        assert!(validate("·leading_middle_dot").is_err());
+        assert!(validate("emoji🦀").is_err());
+        assert_eq!(make_valid("größe (kg)").unwrap(), "größekg");
+        assert_eq!(make_valid("1名前").unwrap(), "名前");
+        assert_eq!(make_valid_ascii("größe").unwrap(), "grosse");
+        assert_eq!(make_valid_ascii("名前").unwrap(), "MingQian");
+        assert_eq!(make_valid_ascii("plain-ascii").unwrap(), "plainascii");
+        assert!(make_valid_ascii("\u{E000}").is_err());
+    }
+
+    #[test]
+    fn test_edition_keywords() {
+        use keywords::Edition::*;
+        assert!(validate_identifier("async", E2015).is_ok());
+        assert!(validate_identifier("async", E2018).is_err());
+        assert!(validate_identifier("dyn", E2015).is_ok());
+        assert!(validate_identifier("dyn", E2021).is_err());
+        assert!(validate_identifier("try", E2015).is_ok());
+        assert!(validate_identifier("try", E2018).is_err());
+        assert!(validate_identifier("gen", E2021).is_ok());
+        assert!(validate_identifier("gen", E2024).is_err());
+        assert!(validate_identifier("r#gen", E2024).is_ok());
+        // weak and retired keywords are fine as identifiers
+        assert!(validate_identifier("union", E2024).is_ok());
+        assert!(validate_identifier("safe", E2024).is_ok());
+        assert!(validate_identifier("proc", E2024).is_ok());
+        assert!(validate_identifier("sizeof", E2024).is_ok());
+
+        assert_eq!(keyword_kind("dyn", E2015), Some(KeywordKind::Weak));
+        assert_eq!(keyword_kind("dyn", E2018), Some(KeywordKind::Strict));
+        assert_eq!(keyword_kind("abstract", E2021), Some(KeywordKind::Reserved));
+        assert_eq!(keyword_kind("gen", E2021), None);
+        assert_eq!(keyword_kind("field", E2024), None);
+
+        let escape = KeywordEscape::Underscore;
+        assert_eq!(make_valid_identifier("await", escape, E2015).unwrap(), "await");
+        assert_eq!(make_valid_identifier("await", escape, E2018).unwrap(), "await_");
+        let raw = KeywordEscape::Raw;
+        assert_eq!(make_valid_identifier("gen", raw, E2024).unwrap(), "r#gen");
     }
 
 }